@@ -0,0 +1,122 @@
+use std::borrow::Cow;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use speedy2d::font::Font;
+
+pub const DIR_NAME: &str = "assets";
+pub const FONT_FILE: &str = "Nasa21-l23X.ttf";
+
+macro_rules! embed {
+    ($($name:literal),* $(,)?) => {
+        &[$(($name, include_bytes!(concat!("../assets/", $name)))),*]
+    };
+}
+
+// Built into the binary with the `embed-assets` feature (on by default), so
+// the game runs from any directory.
+#[cfg(feature = "embed-assets")]
+const EMBEDDED: &[(&str, &[u8])] = embed![
+    "Nasa21-l23X.ttf",
+    "sounds.ini",
+    "Laser_00.ogg",
+    "Laser_01.ogg",
+    "Laser_02.ogg",
+    "Laser_03.ogg",
+    "Laser_04.ogg",
+    "Laser_05.ogg",
+    "Laser_06.ogg",
+    "Laser_07.ogg",
+    "Laser_08.ogg",
+    "Laser_09.ogg",
+    "incoming_00.ogg",
+    "incoming_01.ogg",
+    "incoming_02.ogg",
+    "incoming_03.ogg",
+    "incoming_04.ogg",
+    "part.ogg",
+];
+
+#[cfg(not(feature = "embed-assets"))]
+const EMBEDDED: &[(&str, &[u8])] = embed![];
+
+// Finds asset files by name. A file in the override directory wins over the
+// embedded copy, so single sounds or manifests can be replaced without
+// rebuilding. The override directory is the one given with --assets, or else
+// an `assets` directory next to the executable or in the working directory.
+#[derive(Clone)]
+pub struct Assets {
+    dir: Option<PathBuf>,
+}
+
+impl Assets {
+    pub fn new(dir: Option<PathBuf>) -> Result<Self> {
+        if let Some(dir) = dir {
+            if !dir.is_dir() {
+                bail!("asset directory {} does not exist", dir.display());
+            }
+            return Ok(Assets { dir: Some(dir) });
+        }
+
+        let next_to_exe = std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(|parent| parent.join(DIR_NAME)));
+        let dir = next_to_exe
+            .into_iter()
+            .chain([PathBuf::from(DIR_NAME)])
+            .find(|dir| dir.is_dir());
+        if dir.is_none() && EMBEDDED.is_empty() {
+            bail!("no `{}` directory found and this build has no embedded assets", DIR_NAME);
+        }
+        Ok(Assets { dir })
+    }
+
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    fn override_path(&self, name: &str) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| dir.join(name)).filter(|path| path.is_file())
+    }
+
+    fn embedded(name: &str) -> Option<&'static [u8]> {
+        EMBEDDED.iter().find(|(file, _)| *file == name).map(|(_, bytes)| *bytes)
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        self.override_path(name).is_some() || Assets::embedded(name).is_some()
+    }
+
+    // Where `name` is read from, for error messages.
+    pub fn describe(&self, name: &str) -> String {
+        match self.override_path(name) {
+            Some(path) => path.display().to_string(),
+            None if Assets::embedded(name).is_some() => format!("{} (embedded)", name),
+            None => match &self.dir {
+                Some(dir) => dir.join(name).display().to_string(),
+                None => name.to_string(),
+            },
+        }
+    }
+
+    pub fn read(&self, name: &str) -> Result<Cow<'static, [u8]>> {
+        if let Some(path) = self.override_path(name) {
+            let bytes = fs::read(&path).with_context(|| format!("reading {}", path.display()))?;
+            return Ok(Cow::Owned(bytes));
+        }
+        Assets::embedded(name)
+            .map(Cow::Borrowed)
+            .ok_or_else(|| anyhow!("{} not found", self.describe(name)))
+    }
+
+    pub fn load_font(&self) -> Result<Font> {
+        let bytes = self.read(FONT_FILE)?;
+        Font::new(&bytes).map_err(|err| anyhow!("could not load font {}: {:?}", self.describe(FONT_FILE), err))
+    }
+
+    pub fn read_to_string(&self, name: &str) -> Result<String> {
+        let bytes = self.read(name)?;
+        String::from_utf8(bytes.into_owned()).with_context(|| format!("{} is not valid UTF-8", self.describe(name)))
+    }
+}
@@ -0,0 +1,231 @@
+use speedy2d::color::Color;
+use speedy2d::dimen::{UVec2, Vec2};
+use speedy2d::image::{ImageDataType, ImageHandle, ImageSmoothingMode};
+use speedy2d::Graphics2D;
+
+// Circle diameters in the atlas, in pixels. Each circle is drawn from the
+// smallest one at least as big as it is on screen so edges stay smooth.
+const ATLAS_DIAMETERS: [u32; 5] = [256, 128, 64, 32, 16];
+const PADDING: u32 = 1; // empty pixels around each circle so filtering doesn't bleed
+const GLOW_DIAMETER: u32 = 64; // a soft round highlight, after the circles
+
+struct Slot {
+    diameter: f32,
+    uv: [Vec2; 4], // clockwise from the top left, covering the padded cell
+}
+
+struct Quad {
+    corners: [Vec2; 4],
+    uv: [Vec2; 4],
+    color: Color,
+}
+
+// Collects the circles and lines of a frame and submits them as textured quads
+// that all sample one pre-rendered atlas, so speedy2d can draw them together
+// instead of switching between its circle and shape pipelines for each one.
+// The canvas flushes the batch before anything it can't batch, like text, to
+// keep the drawing order.
+pub struct ShapeBatch {
+    pub enabled: bool,
+    atlas: Option<ImageHandle>,
+    slots: [Slot; ATLAS_DIAMETERS.len()],
+    solid: [Vec2; 4], // a patch inside the largest circle, for lines
+    glow: [Vec2; 4],
+    quads: Vec<Quad>,
+    shapes: usize,
+    flushes: usize,
+    // shapes and flushes in the last finished frame, for the overlay. A flush
+    // is how often the run of atlas quads was cut short by something drawn
+    // another way (a shape drawn directly counts as one), not a count of the
+    // GPU draw calls speedy2d makes, which it decides on its own
+    pub last_shapes: usize,
+    pub last_flushes: usize,
+}
+
+fn circles_width() -> u32 {
+    ATLAS_DIAMETERS.iter().map(|diameter| diameter + 2 * PADDING).sum()
+}
+
+fn atlas_size() -> UVec2 {
+    UVec2::new(circles_width() + GLOW_DIAMETER, ATLAS_DIAMETERS[0] + 2 * PADDING)
+}
+
+// White circles with antialiased edges side by side, then the glow, which
+// fades out from the middle.
+fn render_atlas() -> Vec<u8> {
+    let size = atlas_size();
+    let mut pixels = vec![0u8; (size.x * size.y * 4) as usize];
+    let mut paint = |left: u32, cell: u32, alpha: &dyn Fn(f32) -> f32| {
+        let center = cell as f32 / 2.0;
+        for y in 0..cell {
+            for x in 0..cell {
+                let distance = Vec2::new(x as f32 + 0.5 - center, y as f32 + 0.5 - center).magnitude();
+                let index = ((y * size.x + left + x) * 4) as usize;
+                let alpha = (alpha(distance).clamp(0.0, 1.0) * 255.0).round() as u8;
+                pixels[index..index + 4].copy_from_slice(&[255, 255, 255, alpha]);
+            }
+        }
+    };
+
+    let mut left = 0;
+    for diameter in ATLAS_DIAMETERS {
+        let radius = diameter as f32 / 2.0;
+        paint(left, diameter + 2 * PADDING, &|distance| radius - distance + 0.5);
+        left += diameter + 2 * PADDING;
+    }
+    let radius = GLOW_DIAMETER as f32 / 2.0;
+    paint(left, GLOW_DIAMETER, &|distance| (1.0 - distance / radius).powi(2));
+    pixels
+}
+
+fn corners(left: f32, top: f32, right: f32, bottom: f32) -> [Vec2; 4] {
+    [
+        Vec2::new(left, top),
+        Vec2::new(right, top),
+        Vec2::new(right, bottom),
+        Vec2::new(left, bottom),
+    ]
+}
+
+impl Default for ShapeBatch {
+    fn default() -> Self {
+        ShapeBatch::new(true)
+    }
+}
+
+impl ShapeBatch {
+    pub fn new(enabled: bool) -> Self {
+        let size = atlas_size();
+        let (width, height) = (size.x as f32, size.y as f32);
+        // smallest first, for picking the slot
+        let slots = std::array::from_fn(|index| {
+            let slot = ATLAS_DIAMETERS.len() - 1 - index;
+            let left: u32 = ATLAS_DIAMETERS[..slot].iter().map(|diameter| diameter + 2 * PADDING).sum();
+            let cell = ATLAS_DIAMETERS[slot] + 2 * PADDING;
+            Slot {
+                diameter: ATLAS_DIAMETERS[slot] as f32,
+                uv: corners(left as f32 / width, 0.0, (left + cell) as f32 / width, cell as f32 / height),
+            }
+        });
+
+        let center = Vec2::new(ATLAS_DIAMETERS[0] as f32 / 2.0 + PADDING as f32, height / 2.0);
+        let solid = corners(
+            (center.x - 4.0) / width,
+            (center.y - 4.0) / height,
+            (center.x + 4.0) / width,
+            (center.y + 4.0) / height,
+        );
+
+        let glow_left = circles_width() as f32;
+        let glow = corners(
+            glow_left / width,
+            0.0,
+            (glow_left + GLOW_DIAMETER as f32) / width,
+            GLOW_DIAMETER as f32 / height,
+        );
+
+        ShapeBatch {
+            enabled,
+            atlas: None,
+            slots,
+            solid,
+            glow,
+            quads: Vec::new(),
+            shapes: 0,
+            flushes: 0,
+            last_shapes: 0,
+            last_flushes: 0,
+        }
+    }
+
+    // Takes screen coordinates.
+    pub fn circle(&mut self, center: Vec2, radius: f32, color: Color) {
+        let diameter = radius * 2.0;
+        let slot = self
+            .slots
+            .iter()
+            .find(|slot| slot.diameter >= diameter)
+            .unwrap_or(&self.slots[self.slots.len() - 1]);
+        // grow the quad so the padding maps to the same scale as the circle
+        let half = radius * (slot.diameter + 2.0 * PADDING as f32) / slot.diameter;
+        self.quads.push(Quad {
+            corners: corners(center.x - half, center.y - half, center.x + half, center.y + half),
+            uv: slot.uv,
+            color,
+        });
+        self.shapes += 1;
+    }
+
+    // Takes screen coordinates, and draws the same rectangle as
+    // `Graphics2D::draw_line`.
+    pub fn line(&mut self, start: Vec2, end: Vec2, thickness: f32, color: Color) {
+        let Some(direction) = (end - start).normalize() else {
+            return;
+        };
+        let offset = direction * (thickness / 2.0);
+        let anticlockwise = offset.rotate_90_degrees_anticlockwise();
+        let clockwise = offset.rotate_90_degrees_clockwise();
+        self.quads.push(Quad {
+            corners: [start + anticlockwise, end + anticlockwise, end + clockwise, start + clockwise],
+            uv: self.solid,
+            color,
+        });
+        self.shapes += 1;
+    }
+
+    // Takes screen coordinates. The glow fades out towards `radius`.
+    pub fn glow(&mut self, center: Vec2, radius: f32, color: Color) {
+        self.quads.push(Quad {
+            corners: corners(center.x - radius, center.y - radius, center.x + radius, center.y + radius),
+            uv: self.glow,
+            color,
+        });
+        self.shapes += 1;
+    }
+
+    // Counts a shape the canvas drew directly, so both paths can be compared.
+    pub fn count_immediate(&mut self) {
+        self.shapes += 1;
+        self.flushes += 1;
+    }
+
+    pub fn flush(&mut self, graphics: &mut Graphics2D) {
+        if self.quads.is_empty() {
+            return;
+        }
+        if self.atlas.is_none() {
+            let size = atlas_size();
+            match graphics.create_image_from_raw_pixels(
+                ImageDataType::RGBA,
+                ImageSmoothingMode::Linear,
+                size,
+                &render_atlas(),
+            ) {
+                Ok(atlas) => self.atlas = Some(atlas),
+                Err(err) => {
+                    eprintln!("warning: batched drawing disabled: {:?}", err.error());
+                    self.enabled = false;
+                    self.quads.clear();
+                    return;
+                }
+            }
+        }
+        let Some(atlas) = &self.atlas else {
+            return;
+        };
+        // one call per quad, but speedy2d only queues them and sends a run on
+        // the same texture to the GPU together
+        for quad in self.quads.drain(..) {
+            graphics.draw_quad_image_tinted_four_color(quad.corners, [quad.color; 4], quad.uv, atlas);
+        }
+        self.flushes += 1;
+    }
+
+    // Called once the frame is drawn.
+    pub fn end_frame(&mut self) {
+        self.last_shapes = self.shapes;
+        self.last_flushes = self.flushes;
+        self.shapes = 0;
+        self.flushes = 0;
+    }
+}
@@ -1,13 +1,16 @@
 use std::f32::consts::TAU;
-use rand::random;
+use crate::rng::random;
 use speedy2d::color::Color;
 use speedy2d::dimen::Vec2;
-use speedy2d::Graphics2D;
+use crate::canvas::Canvas;
+use crate::config::GameConfig;
 use crate::{COL_BULLET, COL_ENEMY};
-use crate::game_entity::{ColliderInfo, GameEntity};
+use crate::trail::Trail;
+use crate::game_entity::{next_id, ColliderInfo, GameEntity};
 
 #[derive(Clone)]
 pub struct Bullet {
+    pub id: u32,
     pub pos: Vec2,
     pub vel: Vec2,
     pub radius: f32,
@@ -15,15 +18,17 @@ pub struct Bullet {
     pub health: i32,
     pub layer: u8,
     pub mask: u8,
+    pub trail: Trail,
 }
 
 impl GameEntity for Bullet {
-    fn draw(&self, graphics: &mut Graphics2D) {
-        graphics.draw_circle((self.pos.x, self.pos.y), self.radius, self.color);
+    fn draw(&self, canvas: &mut Canvas) {
+        canvas.draw_circle(self.pos, self.radius, self.color);
     }
 
-    fn update(&mut self, dt: f32) -> bool {
-        self.pos = self.pos + self.vel * dt;
+    fn update(&mut self, dt: f32, _arena_size: Vec2) -> bool {
+        self.pos += self.vel * dt;
+        self.trail.push(self.pos);
         self.health -= 1;
         self.health > 0
     }
@@ -43,33 +48,38 @@ impl GameEntity for Bullet {
 }
 
 impl Bullet {
-    pub fn new(pos: Vec2, vel: Vec2, radius: f32) -> Self {
+    pub fn new(pos: Vec2, vel: Vec2, config: &GameConfig) -> Self {
         Bullet {
+            id: next_id(),
             pos,
             vel,
-            radius,
+            radius: config.bullet_radius,
             color: Color::WHITE,
-            health: 600,
+            health: config.bullet_lifetime,
             layer: COL_BULLET,
             mask: COL_ENEMY,
+            trail: Trail::default(),
         }
     }
 
-    pub fn super_bang(bullets: &mut Vec<Bullet>, num: u32, pos: Vec2) {
+    pub fn super_bang(bullets: &mut Vec<Bullet>, num: u32, pos: Vec2, config: &GameConfig) {
         for _ in 0..num {
             let angle = random::<f32>() * TAU;
-            let speed = random::<f32>() * 200.0 + 100.0;
+            let speed = random::<f32>() * (config.super_bang_max_speed - config.super_bang_min_speed)
+                + config.super_bang_min_speed;
 
             let vel = Vec2::new(angle.cos() * speed, angle.sin() * speed);
 
             let bullet = Bullet {
+                id: next_id(),
                 pos,
                 vel,
-                radius: 2.0,
+                radius: config.super_bang_bullet_radius,
                 color: Color::from_rgba(1.0, 1.0, 1.0, 0.3),
-                health: 200,
+                health: config.super_bang_bullet_lifetime,
                 layer: COL_BULLET,
                 mask: COL_ENEMY,
+                trail: Trail::default(),
             };
 
             bullets.push(bullet);
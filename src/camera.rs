@@ -0,0 +1,127 @@
+use speedy2d::dimen::{UVec2, Vec2};
+
+use crate::canvas::View;
+use crate::game::GameEvent;
+
+const VIEW_WIDTH: f32 = 1280.0; // world units visible at zoom 1
+const VIEW_HEIGHT: f32 = 960.0;
+
+const FOLLOW_SPEED: f32 = 4.0; // how quickly the camera catches up with the player
+const MIN_ZOOM: f32 = 0.5;
+const MAX_ZOOM: f32 = 3.0;
+
+const TRAUMA_DECAY: f32 = 1.5; // trauma lost per second
+const MAX_SHAKE: f32 = 24.0; // offset in world units at full trauma
+
+const TRAUMA_EXPLOSION: f32 = 0.04;
+const TRAUMA_PLAYER_HIT: f32 = 0.35;
+const TRAUMA_SUPER_BANG: f32 = 0.25;
+
+// Decides which part of the arena is on screen. The HUD is laid out in the
+// camera's unzoomed view space, so it never moves or shakes.
+pub struct Camera {
+    pub center: Vec2,
+    pub zoom: f32,
+    pub follow: bool,
+    pub shake_enabled: bool,
+    trauma: f32,
+    shake_offset: Vec2,
+    base_size: Vec2,
+    arena_size: Vec2,
+}
+
+impl Camera {
+    pub fn new(arena_size: Vec2, follow: bool, shake_enabled: bool) -> Self {
+        Camera {
+            center: arena_size / 2.0,
+            zoom: 1.0,
+            follow,
+            shake_enabled,
+            trauma: 0.0,
+            shake_offset: Vec2::ZERO,
+            base_size: Vec2::new(VIEW_WIDTH.min(arena_size.x), VIEW_HEIGHT.min(arena_size.y)),
+            arena_size,
+        }
+    }
+
+    pub fn hud_size(&self) -> Vec2 {
+        self.base_size
+    }
+
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).min(1.0);
+    }
+
+    pub fn zoom_by(&mut self, factor: f32) {
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+    }
+
+    pub fn update(&mut self, dt: f32, target: Vec2, events: &[GameEvent]) {
+        for event in events {
+            match event {
+                GameEvent::Explosion(_) => self.add_trauma(TRAUMA_EXPLOSION),
+                GameEvent::PlayerHit(_) => self.add_trauma(TRAUMA_PLAYER_HIT),
+                GameEvent::SuperBang(_) => self.add_trauma(TRAUMA_SUPER_BANG),
+            }
+        }
+
+        let visible = self.visible_size();
+        let goal = if self.follow { target } else { self.arena_size / 2.0 };
+        self.center += (goal - self.center) * (1.0 - (-FOLLOW_SPEED * dt).exp());
+
+        // keep the view inside the arena, or centered when it shows all of it
+        let clamp_axis = |center: f32, visible: f32, arena: f32| {
+            if visible >= arena {
+                arena / 2.0
+            } else {
+                center.clamp(visible / 2.0, arena - visible / 2.0)
+            }
+        };
+        self.center = Vec2::new(
+            clamp_axis(self.center.x, visible.x, self.arena_size.x),
+            clamp_axis(self.center.y, visible.y, self.arena_size.y),
+        );
+
+        self.trauma = (self.trauma - TRAUMA_DECAY * dt).max(0.0);
+        self.shake_offset = if self.shake_enabled && self.trauma > 0.0 {
+            // shake grows with the square of trauma so small hits stay subtle
+            let shake = self.trauma * self.trauma * MAX_SHAKE;
+            Vec2::new(rand::random::<f32>() * 2.0 - 1.0, rand::random::<f32>() * 2.0 - 1.0) * shake
+        } else {
+            Vec2::ZERO
+        };
+    }
+
+    fn visible_size(&self) -> Vec2 {
+        self.base_size / self.zoom
+    }
+
+    pub fn hud_view(&self, window_size: UVec2) -> View {
+        View::letterbox(window_size, self.base_size)
+    }
+
+    // With `shake` false this gives the steady view, which is what mouse
+    // input should be mapped through.
+    pub fn world_view(&self, window_size: UVec2, shake: bool) -> View {
+        let hud = self.hud_view(window_size);
+        let mut top_left = self.center - self.visible_size() / 2.0;
+        if shake {
+            top_left += self.shake_offset;
+        }
+        let scale = hud.scale * self.zoom;
+        View {
+            offset: hud.offset - top_left * scale,
+            scale,
+        }
+    }
+
+    // Where a point in the arena is in HUD coordinates, ignoring shake.
+    pub fn to_hud(&self, pos: Vec2) -> Vec2 {
+        (pos - (self.center - self.visible_size() / 2.0)) * self.zoom
+    }
+
+    pub fn visible_rect(&self) -> (Vec2, Vec2) {
+        let top_left = self.center + self.shake_offset - self.visible_size() / 2.0;
+        (top_left, top_left + self.visible_size())
+    }
+}
@@ -0,0 +1,226 @@
+use speedy2d::color::Color;
+use speedy2d::dimen::{UVec2, Vec2};
+use speedy2d::font::FormattedTextBlock;
+use speedy2d::shape::Rectangle;
+use speedy2d::Graphics2D;
+
+use crate::batch::ShapeBatch;
+use crate::raster::Raster;
+
+// Maps arena (world) coordinates to window pixels. The arena keeps its aspect
+// ratio and is centered in the window, leaving black bars on the sides that
+// don't fit.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct View {
+    pub offset: Vec2,
+    pub scale: f32,
+}
+
+impl View {
+    pub fn letterbox(window_size: UVec2, arena_size: Vec2) -> Self {
+        let window = Vec2::new(window_size.x as f32, window_size.y as f32);
+        let scale = (window.x / arena_size.x).min(window.y / arena_size.y).max(0.0001);
+        View {
+            offset: (window - arena_size * scale) / 2.0,
+            scale,
+        }
+    }
+
+    pub fn to_screen(self, pos: Vec2) -> Vec2 {
+        pos * self.scale + self.offset
+    }
+
+    pub fn to_world(self, pos: Vec2) -> Vec2 {
+        (pos - self.offset) / self.scale
+    }
+}
+
+// A circle, line or glow as it was drawn, in world coordinates. Clips keep
+// the last seconds of play as lists of these.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Shape {
+    Circle { pos: Vec2, radius: f32, color: Color },
+    Line { start: Vec2, end: Vec2, thickness: f32, color: Color },
+    Glow { pos: Vec2, radius: f32, color: Color },
+}
+
+// Where a canvas draws to: the window, an image in memory, or a list of
+// shapes to be drawn later.
+enum Target<'a> {
+    Window(&'a mut Graphics2D),
+    Image(&'a mut Raster),
+    Record(&'a mut Vec<Shape>),
+}
+
+// Everything in the world is drawn through a canvas so entities can keep
+// working in arena coordinates regardless of the window size. With a shape
+// batch, circles and lines are collected and drawn together; anything else
+// flushes the batch first so the drawing order stays the same.
+pub struct Canvas<'a> {
+    target: Target<'a>,
+    pub view: View,
+    batch: Option<&'a mut ShapeBatch>,
+}
+
+impl<'a> Canvas<'a> {
+    pub fn new(graphics: &'a mut Graphics2D, view: View) -> Self {
+        Canvas {
+            target: Target::Window(graphics),
+            view,
+            batch: None,
+        }
+    }
+
+    pub fn with_batch(graphics: &'a mut Graphics2D, view: View, batch: &'a mut ShapeBatch) -> Self {
+        Canvas {
+            target: Target::Window(graphics),
+            view,
+            batch: Some(batch),
+        }
+    }
+
+    // Draws into an image instead of the window. Text is left out, as the
+    // software renderer has no font rendering.
+    pub fn offscreen(raster: &'a mut Raster, view: View) -> Self {
+        Canvas {
+            target: Target::Image(raster),
+            view,
+            batch: None,
+        }
+    }
+
+    // Collects circles, lines and glows instead of drawing them. The view
+    // changes nothing, so the shapes keep world coordinates; rectangles, text
+    // and clipping are left out.
+    pub fn recording(shapes: &'a mut Vec<Shape>) -> Self {
+        Canvas {
+            target: Target::Record(shapes),
+            view: View {
+                offset: Vec2::ZERO,
+                scale: 1.0,
+            },
+            batch: None,
+        }
+    }
+
+    pub fn draw_shape(&mut self, shape: &Shape) {
+        match *shape {
+            Shape::Circle { pos, radius, color } => self.draw_circle(pos, radius, color),
+            Shape::Line { start, end, thickness, color } => self.draw_line(start, end, thickness, color),
+            Shape::Glow { pos, radius, color } => self.draw_glow(pos, radius, color),
+        }
+    }
+
+    pub fn draw_circle(&mut self, pos: Vec2, radius: f32, color: Color) {
+        let (center, radius) = (self.view.to_screen(pos), radius * self.view.scale);
+        match (&mut self.target, &mut self.batch) {
+            (Target::Image(raster), _) => raster.draw_circle(center, radius, color),
+            (Target::Record(shapes), _) => shapes.push(Shape::Circle { pos: center, radius, color }),
+            (Target::Window(_), Some(batch)) if batch.enabled => batch.circle(center, radius, color),
+            (Target::Window(graphics), batch) => {
+                if let Some(batch) = batch {
+                    batch.count_immediate();
+                }
+                graphics.draw_circle(center, radius, color);
+            }
+        }
+    }
+
+    pub fn draw_line(&mut self, start: Vec2, end: Vec2, thickness: f32, color: Color) {
+        let (start, end) = (self.view.to_screen(start), self.view.to_screen(end));
+        let thickness = thickness * self.view.scale;
+        match (&mut self.target, &mut self.batch) {
+            (Target::Image(raster), _) => raster.draw_line(start, end, thickness, color),
+            (Target::Record(shapes), _) => shapes.push(Shape::Line { start, end, thickness, color }),
+            (Target::Window(_), Some(batch)) if batch.enabled => batch.line(start, end, thickness, color),
+            (Target::Window(graphics), batch) => {
+                if let Some(batch) = batch {
+                    batch.count_immediate();
+                }
+                graphics.draw_line(start, end, thickness, color);
+            }
+        }
+    }
+
+    // A soft halo. speedy2d only blends by alpha, so rather than adding light
+    // this lays a translucent, bright tint over what is below. Without the
+    // batch's glow sprite it is approximated by stacked circles.
+    pub fn draw_glow(&mut self, pos: Vec2, radius: f32, color: Color) {
+        let (center, radius) = (self.view.to_screen(pos), radius * self.view.scale);
+        match (&mut self.target, &mut self.batch) {
+            (Target::Image(raster), _) => raster.draw_glow(center, radius, color),
+            (Target::Record(shapes), _) => shapes.push(Shape::Glow { pos: center, radius, color }),
+            (Target::Window(_), Some(batch)) if batch.enabled => batch.glow(center, radius, color),
+            (Target::Window(graphics), batch) => {
+                let layer = Color::from_rgba(color.r(), color.g(), color.b(), color.a() / 3.0);
+                for step in 1..=3 {
+                    if let Some(batch) = batch.as_deref_mut() {
+                        batch.count_immediate();
+                    }
+                    graphics.draw_circle(center, radius * step as f32 / 3.0, layer);
+                }
+            }
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let (Target::Window(graphics), Some(batch)) = (&mut self.target, &mut self.batch) {
+            batch.flush(graphics);
+        }
+    }
+
+    pub fn draw_rectangle(&mut self, rect: &Rectangle, color: Color) {
+        self.flush();
+        let rect = Rectangle::new(
+            self.view.to_screen(*rect.top_left()),
+            self.view.to_screen(*rect.bottom_right()),
+        );
+        match &mut self.target {
+            Target::Window(graphics) => graphics.draw_rectangle(rect, color),
+            Target::Image(raster) => raster.draw_rectangle(&rect, color),
+            Target::Record(_) => {}
+        }
+    }
+
+    // Text is laid out by the caller at `font_size(size)` so it stays sharp.
+    pub fn draw_text(&mut self, pos: Vec2, color: Color, text: &FormattedTextBlock) {
+        self.flush();
+        if let Target::Window(graphics) = &mut self.target {
+            graphics.draw_text(self.view.to_screen(pos), color, text);
+        }
+    }
+
+    pub fn font_size(&self, size: f32) -> f32 {
+        size * self.view.scale
+    }
+
+    // Restricts drawing to the arena so nothing spills into the letterbox bars.
+    pub fn clip_to(&mut self, arena_size: Vec2) {
+        self.flush();
+        let top_left = self.view.to_screen(Vec2::ZERO);
+        let bottom_right = self.view.to_screen(arena_size);
+        self.set_clip(Some(Rectangle::from_tuples(
+            (top_left.x.floor() as i32, top_left.y.floor() as i32),
+            (bottom_right.x.ceil() as i32, bottom_right.y.ceil() as i32),
+        )));
+    }
+
+    pub fn clear_clip(&mut self) {
+        self.flush();
+        self.set_clip(None);
+    }
+
+    fn set_clip(&mut self, clip: Option<Rectangle<i32>>) {
+        match &mut self.target {
+            Target::Window(graphics) => graphics.set_clip(clip),
+            Target::Image(raster) => raster.set_clip(clip),
+            Target::Record(_) => {}
+        }
+    }
+}
+
+impl Drop for Canvas<'_> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
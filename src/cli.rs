@@ -0,0 +1,474 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::clip::{self, ClipFormat};
+use crate::config::GameConfig;
+use crate::game::MAX_LEVEL;
+use crate::ini;
+use crate::input::{self, Control, KeyBindings};
+use crate::palette::{EnemyStyle, Palette};
+use crate::sound::AudioSettings;
+use crate::spectate;
+use crate::trail::EffectsStyle;
+
+// Read at startup when it exists and no --config is given. Settings changed
+// in the game are saved to whichever config file is in use.
+pub const DEFAULT_CONFIG: &str = "colorbang.ini";
+
+pub const HELP: &str = "\
+Color Bang! - a fast-paced 2D arcade shooter
+
+USAGE:
+    colorbang [OPTIONS]
+    colorbang render-sound [--assets <DIR>] <PRESET> <FILE.wav>
+
+COMMANDS:
+    render-sound            Render a synth preset from assets/sounds.ini to a WAV file
+
+OPTIONS:
+    --width <PIXELS>        Window width in scaled pixels [default: 1280]
+    --height <PIXELS>       Window height in scaled pixels [default: 960]
+    --arena-width <UNITS>   Width of the playing field [default: 1280]
+    --arena-height <UNITS>  Height of the playing field [default: 960]
+    --fullscreen            Start in borderless fullscreen
+    --vsync / --no-vsync    Enable or disable vsync [default: enabled]
+    --no-camera-follow      Keep the camera still instead of following the player
+    --no-screen-shake       Disable screen shake
+    --minimap               Show the whole arena in a corner of the screen
+    --palette <NAME>        Enemy colors: random, deuteranopia, protanopia, tritanopia or
+                            high-contrast [default: random]
+    --enemy-marks           Draw a symbol on each enemy matching its color
+    --effects <STYLE>       trails (per-object trails and glow) or smear (fade the whole
+                            screen) [default: trails]
+    --clip-length <SECONDS> Seconds kept for saving a clip with F9, 10 to 30 [default: 15]
+    --clip-format <FORMAT>  gif, or png for a directory of numbered frames [default: gif]
+    --no-batching           Draw every circle on its own instead of batching them (F4 in game)
+    --seed <N>              Seed for enemy spawns and super bangs
+    --level <N>             Starting level, 1 to 1000 [default: 1]
+    --difficulty <NAME>     easy, normal, hard or insane [default: normal]
+    --mute                  Disable all sound
+    --record <FILE>         Record the session to a replay file
+    --replay <FILE>         Play back a recorded replay file
+    --headless              Run the simulation without a window (needs --replay or --ticks)
+    --ticks <N>             Number of fixed 1/60 s steps to simulate in headless mode
+    --render-frame <TICK>   Simulate TICK frames (of the replay, or 1/60 s steps) and render
+                            the arena to the --out image instead of opening a window
+    --out <FILE.png>        Image written by --render-frame, --width by --height pixels
+    --assets <DIR>          Load assets from this directory instead of the built-in ones
+                            [default: `assets` next to the executable or in the working directory]
+    --config <FILE>         Read defaults for these options from a `key = value` file
+                            [default: colorbang.ini, if it exists]
+    --spectate [PORT]       Broadcast the game to spectators on this machine [default port: 7878]
+    --spectate-public       Let spectators on other machines connect too
+    --watch <HOST:PORT>     Connect to a spectator server and watch read-only
+    -h, --help              Print this help
+";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+    Insane,
+}
+
+impl Difficulty {
+    pub fn parse(name: &str) -> Option<Difficulty> {
+        match name.to_ascii_lowercase().as_str() {
+            "easy" => Some(Difficulty::Easy),
+            "normal" => Some(Difficulty::Normal),
+            "hard" => Some(Difficulty::Hard),
+            "insane" => Some(Difficulty::Insane),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+            Difficulty::Insane => "insane",
+        }
+    }
+}
+
+pub struct Options {
+    pub width: u32,
+    pub height: u32,
+    pub arena_width: Option<f32>,
+    pub arena_height: Option<f32>,
+    pub fullscreen: bool,
+    pub vsync: bool,
+    pub camera_follow: bool,
+    pub screen_shake: bool,
+    pub minimap: bool,
+    pub enemy_style: EnemyStyle,
+    pub batching: bool,
+    pub effects: EffectsStyle,
+    pub clip_length: f32,
+    pub clip_format: ClipFormat,
+    pub seed: Option<u64>,
+    pub level: u32,
+    pub difficulty: Difficulty,
+    pub mute: bool,
+    pub record: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    pub headless: bool,
+    pub ticks: Option<u32>,
+    pub render_frame: Option<u32>,
+    pub out: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+    pub spectate: Option<u16>,
+    pub spectate_public: bool,
+    pub watch: Option<String>,
+    pub assets: Option<PathBuf>,
+    pub audio: AudioSettings,
+    pub keys: KeyBindings,
+    // `[game]` overrides from the config file, optionally for one difficulty
+    pub tuning: Vec<(Option<Difficulty>, String, String)>,
+}
+
+pub enum Command {
+    Run(Box<Options>),
+    RenderSound {
+        preset: String,
+        out: PathBuf,
+        assets: Option<PathBuf>,
+    },
+    Help,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            width: 1280,
+            height: 960,
+            arena_width: None,
+            arena_height: None,
+            fullscreen: false,
+            vsync: true,
+            camera_follow: true,
+            screen_shake: true,
+            minimap: false,
+            enemy_style: EnemyStyle::default(),
+            batching: true,
+            effects: EffectsStyle::Trails,
+            clip_length: clip::DEFAULT_LENGTH,
+            clip_format: ClipFormat::Gif,
+            seed: None,
+            level: 1,
+            difficulty: Difficulty::Normal,
+            mute: false,
+            record: None,
+            replay: None,
+            headless: false,
+            ticks: None,
+            render_frame: None,
+            out: None,
+            config: None,
+            spectate: None,
+            spectate_public: false,
+            watch: None,
+            assets: None,
+            audio: AudioSettings::default(),
+            keys: KeyBindings::default(),
+            tuning: Vec::new(),
+        }
+    }
+}
+
+fn parse_flag(name: &str, value: Option<&str>) -> Result<bool> {
+    match value {
+        None | Some("true") | Some("yes") | Some("on") | Some("1") => Ok(true),
+        Some("false") | Some("no") | Some("off") | Some("0") => Ok(false),
+        Some(value) => bail!("invalid value `{}` for --{}: expected true or false", value, name),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: Option<&str>) -> Result<T> {
+    let value = value.ok_or_else(|| anyhow!("--{} needs a value", name))?;
+    value
+        .parse()
+        .map_err(|_| anyhow!("invalid value `{}` for --{}", value, name))
+}
+
+fn required<'a>(name: &str, value: Option<&'a str>) -> Result<&'a str> {
+    value.ok_or_else(|| anyhow!("--{} needs a value", name))
+}
+
+// Options that take a value. Everything else is a flag.
+fn takes_value(name: &str) -> bool {
+    matches!(
+        name,
+        "width" | "height" | "arena-width" | "arena-height" | "seed" | "level" | "difficulty"
+            | "record" | "replay" | "ticks" | "config" | "watch" | "assets" | "effects"
+            | "render-frame" | "out" | "clip-length" | "clip-format" | "palette"
+    )
+}
+
+impl Options {
+    fn apply(&mut self, name: &str, value: Option<&str>) -> Result<()> {
+        match name {
+            "width" => self.width = parse_number(name, value)?,
+            "height" => self.height = parse_number(name, value)?,
+            "arena-width" => self.arena_width = Some(parse_number(name, value)?),
+            "arena-height" => self.arena_height = Some(parse_number(name, value)?),
+            "fullscreen" => self.fullscreen = parse_flag(name, value)?,
+            "vsync" => self.vsync = parse_flag(name, value)?,
+            "no-vsync" => self.vsync = !parse_flag(name, value)?,
+            "no-camera-follow" => self.camera_follow = !parse_flag(name, value)?,
+            "no-screen-shake" => self.screen_shake = !parse_flag(name, value)?,
+            "minimap" => self.minimap = parse_flag(name, value)?,
+            "palette" => {
+                let value = required(name, value)?;
+                self.enemy_style.palette = Palette::parse(value).ok_or_else(|| {
+                    anyhow!(
+                        "unknown palette `{}`, expected random, deuteranopia, protanopia, tritanopia or high-contrast",
+                        value
+                    )
+                })?;
+            }
+            "enemy-marks" => self.enemy_style.marks = parse_flag(name, value)?,
+            "no-batching" => self.batching = !parse_flag(name, value)?,
+            "seed" => self.seed = Some(parse_number(name, value)?),
+            "level" => self.level = parse_number(name, value)?,
+            "difficulty" => {
+                let value = required(name, value)?;
+                self.difficulty = Difficulty::parse(value)
+                    .ok_or_else(|| anyhow!("unknown difficulty `{}`", value))?;
+            }
+            "effects" => {
+                let value = required(name, value)?;
+                self.effects = EffectsStyle::parse(value)
+                    .ok_or_else(|| anyhow!("unknown effects style `{}`, expected trails or smear", value))?;
+            }
+            "clip-length" => self.clip_length = parse_number(name, value)?,
+            "clip-format" => {
+                let value = required(name, value)?;
+                self.clip_format = ClipFormat::parse(value)
+                    .ok_or_else(|| anyhow!("unknown clip format `{}`, expected gif or png", value))?;
+            }
+            "mute" => self.mute = parse_flag(name, value)?,
+            "record" => self.record = Some(PathBuf::from(required(name, value)?)),
+            "replay" => self.replay = Some(PathBuf::from(required(name, value)?)),
+            "headless" => self.headless = parse_flag(name, value)?,
+            "ticks" => self.ticks = Some(parse_number(name, value)?),
+            "render-frame" => self.render_frame = Some(parse_number(name, value)?),
+            "out" => self.out = Some(PathBuf::from(required(name, value)?)),
+            "spectate" => {
+                self.spectate = match value {
+                    Some(port) => Some(parse_number(name, Some(port))?),
+                    None => Some(spectate::DEFAULT_PORT),
+                }
+            }
+            "spectate-public" => self.spectate_public = parse_flag(name, value)?,
+            "watch" => self.watch = Some(required(name, value)?.to_string()),
+            "assets" => self.assets = Some(PathBuf::from(required(name, value)?)),
+            _ => bail!("unknown option --{}", name),
+        }
+        Ok(())
+    }
+
+    fn load_config(&mut self, path: PathBuf) -> Result<()> {
+        for entry in ini::load(&path)? {
+            let result = match entry.section.as_str() {
+                "" => self.apply(&entry.key, Some(&entry.value)),
+                "audio" => self.apply_audio(&entry.key, &entry.value),
+                "keys" => self.apply_key(&entry.key, &entry.value),
+                "game" => self.add_tuning(None, &entry.key, &entry.value),
+                section => match section.strip_prefix("game.") {
+                    Some(name) => match Difficulty::parse(name) {
+                        Some(difficulty) => self.add_tuning(Some(difficulty), &entry.key, &entry.value),
+                        None => Err(anyhow!("unknown difficulty in [{}]", section)),
+                    },
+                    None => continue,
+                },
+            };
+            result.with_context(|| format!("{} line {}", path.display(), entry.line))?;
+        }
+        self.config = Some(path);
+        Ok(())
+    }
+
+    fn apply_audio(&mut self, key: &str, value: &str) -> Result<()> {
+        let volume = self
+            .audio
+            .get_mut(key)
+            .ok_or_else(|| anyhow!("unknown audio setting `{}`", key))?;
+        *volume = value
+            .parse::<f32>()
+            .ok()
+            .filter(|volume| (0.0..=1.0).contains(volume))
+            .ok_or_else(|| anyhow!("invalid volume `{}` for {}: expected 0 to 1", value, key))?;
+        Ok(())
+    }
+
+    fn apply_key(&mut self, name: &str, value: &str) -> Result<()> {
+        let control = Control::from_name(name).ok_or_else(|| anyhow!("unknown control `{}`", name))?;
+        let key = input::parse_key(value).ok_or_else(|| anyhow!("unknown key `{}` for {}", value, name))?;
+        self.keys.set(control, key);
+        Ok(())
+    }
+
+    fn add_tuning(&mut self, difficulty: Option<Difficulty>, key: &str, value: &str) -> Result<()> {
+        // catch unknown keys and bad values while the line number is known
+        GameConfig::default().set(key, value)?;
+        self.tuning.push((difficulty, key.to_string(), value.to_string()));
+        Ok(())
+    }
+
+    // The preset for the chosen difficulty with the config file's overrides.
+    pub fn game_config(&self) -> Result<GameConfig> {
+        let mut config = GameConfig::preset(self.difficulty);
+        for (difficulty, key, value) in &self.tuning {
+            if difficulty.is_none_or(|difficulty| difficulty == self.difficulty) {
+                config.set(key, value)?;
+            }
+        }
+        config.validate().context("invalid [game] settings")?;
+        Ok(config)
+    }
+
+    pub fn config_path(&self) -> PathBuf {
+        self.config.clone().unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG))
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.width == 0 || self.height == 0 {
+            bail!("--width and --height must be greater than zero");
+        }
+        if [self.arena_width, self.arena_height].iter().flatten().any(|size| size.is_nan() || *size < 100.0) {
+            bail!("--arena-width and --arena-height must be at least 100");
+        }
+        if self.replay.is_some() && (self.arena_width.is_some() || self.arena_height.is_some()) {
+            bail!("--arena-width and --arena-height cannot be used with --replay; the replay file stores its own arena size");
+        }
+        if !(clip::MIN_LENGTH..=clip::MAX_LENGTH).contains(&self.clip_length) {
+            bail!("--clip-length must be between {} and {} seconds", clip::MIN_LENGTH, clip::MAX_LENGTH);
+        }
+        if self.level == 0 {
+            bail!("--level must be at least 1");
+        }
+        if self.level > MAX_LEVEL {
+            bail!("--level must be at most {}", MAX_LEVEL);
+        }
+        if self.replay.is_some() && self.record.is_some() {
+            bail!("--replay and --record cannot be used together");
+        }
+        if self.replay.is_some() && self.seed.is_some() {
+            bail!("--seed cannot be used with --replay; the replay file stores its own seed");
+        }
+        if self.spectate_public && self.spectate.is_none() {
+            bail!("--spectate-public needs --spectate");
+        }
+        if self.watch.is_some() {
+            if self.spectate.is_some() {
+                bail!("--watch and --spectate cannot be used together");
+            }
+            if self.headless || self.replay.is_some() || self.record.is_some() || self.seed.is_some() {
+                bail!("--watch only renders a remote game and cannot be combined with gameplay options");
+            }
+        }
+        if self.headless {
+            if self.fullscreen {
+                bail!("--headless cannot be combined with --fullscreen");
+            }
+            if self.spectate.is_some() {
+                bail!("--headless cannot be combined with --spectate");
+            }
+            if self.replay.is_none() && self.ticks.is_none() {
+                bail!("--headless needs either --replay <FILE> or --ticks <N>");
+            }
+        } else if self.ticks.is_some() {
+            bail!("--ticks is only valid with --headless");
+        }
+        if self.render_frame.is_some() != self.out.is_some() {
+            bail!("--render-frame and --out must be used together");
+        }
+        if self.render_frame.is_some()
+            && (self.headless || self.fullscreen || self.spectate.is_some() || self.watch.is_some() || self.record.is_some())
+        {
+            bail!("--render-frame renders a single image and cannot be combined with --headless, --fullscreen, --spectate, --watch or --record");
+        }
+        Ok(())
+    }
+}
+
+// Reads a config file on its own, for picking up changes while the game runs.
+pub fn read_config(path: &Path) -> Result<Options> {
+    let mut options = Options::default();
+    options.load_config(path.to_path_buf())?;
+    Ok(options)
+}
+
+fn parse_render_sound(args: &[String]) -> Result<Command> {
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        return Ok(Command::Help);
+    }
+    let mut assets = None;
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--assets" {
+            assets = Some(PathBuf::from(required("assets", args.next().map(String::as_str))?));
+        } else if arg.starts_with("--") {
+            bail!("unknown option {} for render-sound", arg);
+        } else {
+            positional.push(arg);
+        }
+    }
+    match positional[..] {
+        [preset, out] => Ok(Command::RenderSound {
+            preset: preset.clone(),
+            out: PathBuf::from(out),
+            assets,
+        }),
+        _ => bail!("render-sound expects a preset name and an output file"),
+    }
+}
+
+pub fn parse(args: &[String]) -> Result<Command> {
+    if args.first().is_some_and(|arg| arg == "render-sound") {
+        return parse_render_sound(&args[1..]);
+    }
+
+    let mut options = Options::default();
+
+    // the config file only provides defaults, so load it before anything else
+    if let Some(index) = args.iter().position(|arg| arg == "--config") {
+        let path = args
+            .get(index + 1)
+            .ok_or_else(|| anyhow!("--config needs a value"))?;
+        options.load_config(PathBuf::from(path))?;
+    } else if Path::new(DEFAULT_CONFIG).exists() {
+        options.load_config(PathBuf::from(DEFAULT_CONFIG))?;
+    }
+
+    let mut args = args.iter().peekable();
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            return Ok(Command::Help);
+        }
+
+        let name = arg
+            .strip_prefix("--")
+            .ok_or_else(|| anyhow!("unexpected argument `{}`", arg))?;
+
+        if name == "config" {
+            args.next();
+        } else if takes_value(name) {
+            options.apply(name, args.next().map(String::as_str))?;
+        } else if name == "spectate" {
+            let port = args.next_if(|next| !next.starts_with("--"));
+            options.apply(name, port.map(String::as_str))?;
+        } else {
+            options.apply(name, None)?;
+        }
+    }
+
+    options.validate()?;
+    options.game_config()?;
+    Ok(Command::Run(Box::new(options)))
+}
@@ -0,0 +1,210 @@
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use anyhow::{anyhow, Context, Result};
+use image::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, RgbaImage};
+use speedy2d::dimen::{UVec2, Vec2};
+
+use crate::canvas::{Canvas, Shape};
+use crate::game::Game;
+use crate::render::{self, Scene};
+use crate::trail::EffectsStyle;
+
+pub const DEFAULT_LENGTH: f32 = 15.0; // seconds
+pub const MIN_LENGTH: f32 = 10.0;
+pub const MAX_LENGTH: f32 = 30.0;
+
+const FPS: u32 = 25; // GIF frame delays are in hundredths of a second
+const WIDTH: u32 = 640; // the height follows the arena's aspect ratio
+const GIF_SPEED: i32 = 10; // color quantization, 1 (best) to 30 (fastest)
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ClipFormat {
+    Gif,
+    Png,
+}
+
+impl ClipFormat {
+    pub fn parse(name: &str) -> Option<ClipFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "gif" => Some(ClipFormat::Gif),
+            "png" => Some(ClipFormat::Png),
+            _ => None,
+        }
+    }
+}
+
+// One frame as the shapes the game drew, in drawing order. Trails and glows
+// are kept apart so the effects style can still be chosen when saving.
+struct Snapshot {
+    arena_size: Vec2,
+    effects: Vec<Shape>,
+    entities: Vec<Shape>,
+}
+
+impl Snapshot {
+    fn record(game: &Game) -> Self {
+        let scene = Scene::from(game);
+        let mut effects = Vec::new();
+        render::draw_effects(&mut Canvas::recording(&mut effects), &scene);
+        let mut entities = Vec::new();
+        render::draw_entities(&mut Canvas::recording(&mut entities), &scene);
+        Snapshot {
+            arena_size: game.arena_size,
+            effects,
+            entities,
+        }
+    }
+
+    fn draw(&self, canvas: &mut Canvas, effects: EffectsStyle) {
+        if effects == EffectsStyle::Trails {
+            for shape in &self.effects {
+                canvas.draw_shape(shape);
+            }
+        }
+        for shape in &self.entities {
+            canvas.draw_shape(shape);
+        }
+    }
+}
+
+// Keeps the last few seconds of the game as snapshots, which are far smaller
+// than rendered frames, and renders them to a GIF or a numbered PNG sequence
+// on a background thread when asked.
+pub struct ClipRecorder {
+    snapshots: VecDeque<Arc<Snapshot>>,
+    capacity: usize,
+    since_snapshot: f32,
+    format: ClipFormat,
+    saving: Arc<AtomicBool>,
+}
+
+impl ClipRecorder {
+    pub fn new(length: f32, format: ClipFormat) -> Self {
+        let capacity = (length * FPS as f32).round() as usize;
+        ClipRecorder {
+            snapshots: VecDeque::with_capacity(capacity),
+            capacity,
+            since_snapshot: 0.0,
+            format,
+            saving: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    // Called after every game tick; takes a snapshot FPS times per second.
+    pub fn record(&mut self, dt: f32, game: &Game) {
+        let interval = 1.0 / FPS as f32;
+        self.since_snapshot += dt;
+        if self.since_snapshot < interval {
+            return;
+        }
+        // don't try to catch up after a long frame
+        self.since_snapshot = (self.since_snapshot - interval).min(interval);
+
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(Arc::new(Snapshot::record(game)));
+    }
+
+    pub fn save(&mut self, effects: EffectsStyle) {
+        if self.snapshots.is_empty() {
+            return;
+        }
+        if self.saving.swap(true, Ordering::SeqCst) {
+            println!("Still saving the previous clip");
+            return;
+        }
+
+        let snapshots: Vec<Arc<Snapshot>> = self.snapshots.iter().cloned().collect();
+        let format = self.format;
+        let saving = self.saving.clone();
+        // PNG frames go into a directory of the same name
+        let path = render::timestamped_path("gif");
+        let path = match format {
+            ClipFormat::Gif => path,
+            ClipFormat::Png => path.with_extension(""),
+        };
+        println!(
+            "Saving the last {:.1} s to {}",
+            snapshots.len() as f32 / FPS as f32,
+            path.display()
+        );
+
+        thread::spawn(move || {
+            match write_clip(&snapshots, format, effects, &path) {
+                Ok(()) => println!("Saved clip to {}", path.display()),
+                Err(err) => eprintln!("warning: could not save clip: {:#}", err),
+            }
+            saving.store(false, Ordering::SeqCst);
+        });
+    }
+}
+
+fn write_clip(snapshots: &[Arc<Snapshot>], format: ClipFormat, effects: EffectsStyle, path: &Path) -> Result<()> {
+    let arena_size = snapshots[0].arena_size;
+    // even sizes are friendlier to video tools the frames may be fed to
+    let height = ((WIDTH as f32 * arena_size.y / arena_size.x / 2.0).round() as u32 * 2).max(2);
+    let size = UVec2::new(WIDTH, height);
+    let frames = snapshots
+        .iter()
+        .map(|snapshot| render::render_with(arena_size, size, |canvas| snapshot.draw(canvas, effects)));
+
+    match format {
+        ClipFormat::Gif => {
+            let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+            let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), GIF_SPEED);
+            encoder.set_repeat(Repeat::Infinite)?;
+            for raster in frames {
+                let image = RgbaImage::from_raw(size.x, size.y, raster.to_rgba8())
+                    .ok_or_else(|| anyhow!("rendered frame has the wrong size"))?;
+                let delay = Delay::from_numer_denom_ms(1000, FPS);
+                encoder.encode_frame(Frame::from_parts(image, 0, 0, delay))?;
+            }
+        }
+        ClipFormat::Png => {
+            fs::create_dir_all(path).with_context(|| format!("creating {}", path.display()))?;
+            for (index, raster) in frames.enumerate() {
+                raster.save_png(&path.join(format!("frame-{:04}.png", index + 1)))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Difficulty;
+    use crate::config::GameConfig;
+    use crate::input::Action;
+    use crate::rng;
+    use crate::sound::SoundPlayer;
+
+    // A recorded frame has to look exactly like the game drawn directly.
+    #[test]
+    fn snapshot_draws_like_the_game() {
+        rng::seed_cosmetic(3);
+        let arena_size = Vec2::new(crate::ARENA_WIDTH, crate::ARENA_HEIGHT);
+        let config = GameConfig::preset(Difficulty::Normal);
+        let mut game = Game::new(3, 5, Difficulty::Normal, config, arena_size, SoundPlayer::null());
+        game.handle_action(Action::FireStart);
+        for _ in 0..90 {
+            game.tick(1.0 / 60.0);
+        }
+
+        let snapshot = Snapshot::record(&game);
+        let size = UVec2::new(320, 240);
+        for effects in [EffectsStyle::Smear, EffectsStyle::Trails] {
+            let direct = render::render_frame(&Scene::from(&game), effects, size);
+            let replayed = render::render_with(arena_size, size, |canvas| snapshot.draw(canvas, effects));
+            assert!(direct.to_rgba8() == replayed.to_rgba8(), "{:?} frames differ", effects);
+        }
+    }
+}
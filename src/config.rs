@@ -0,0 +1,246 @@
+use anyhow::{anyhow, bail, Result};
+
+use crate::cli::Difficulty;
+
+const MAX_ENEMIES_PER_LEVEL: u32 = 100;
+const MAX_DEBUG_WAVE: u32 = 1000;
+const MAX_PARTICLE_BUDGET: u32 = 100_000;
+
+// Settings are numbers of different types; only floats can be infinite or NaN.
+trait Finite {
+    fn finite(&self) -> bool;
+}
+
+impl Finite for f32 {
+    fn finite(&self) -> bool {
+        self.is_finite()
+    }
+}
+
+impl Finite for i32 {
+    fn finite(&self) -> bool {
+        true
+    }
+}
+
+impl Finite for u32 {
+    fn finite(&self) -> bool {
+        true
+    }
+}
+
+macro_rules! game_config {
+    ($($key:ident: $ty:ty = $default:expr,)*) => {
+        // Gameplay tuning. Every difficulty is a preset of these values, and
+        // any of them can be overridden in the `[game]` section of the config
+        // file (or `[game.hard]` and so on for a single difficulty).
+        #[derive(Clone, Copy, PartialEq, Debug)]
+        pub struct GameConfig {
+            $(pub $key: $ty,)*
+        }
+
+        impl Default for GameConfig {
+            fn default() -> Self {
+                GameConfig {
+                    $($key: $default,)*
+                }
+            }
+        }
+
+        impl GameConfig {
+            pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+                match key {
+                    $(stringify!($key) => {
+                        self.$key = value
+                            .parse()
+                            .map_err(|_| anyhow!("invalid value `{}` for {}", value, key))?
+                    })*
+                    _ => bail!("unknown game setting `{}`", key),
+                }
+                Ok(())
+            }
+
+            // The first setting that is infinite or not a number.
+            fn non_finite(&self) -> Option<&'static str> {
+                $(if !self.$key.finite() {
+                    return Some(stringify!($key));
+                })*
+                None
+            }
+
+            // Every setting with its current value, in declaration order.
+            pub fn values(&self) -> Vec<(&'static str, String)> {
+                vec![$((stringify!($key), self.$key.to_string()),)*]
+            }
+        }
+    };
+}
+
+game_config! {
+    // the player's size is their health
+    player_radius: f32 = 20.0,
+    // radius lost per enemy hit
+    player_damage: f32 = 1.0,
+    player_speed: f32 = 100.0,
+    // radians per second when turning with the keyboard
+    player_turn_speed: f32 = 5.0,
+    player_drag: f32 = 50.0,
+
+    // seconds between two shots
+    fire_cooldown: f32 = 0.2,
+    bullet_speed: f32 = 200.0,
+    bullet_radius: f32 = 5.0,
+    // in frames
+    bullet_lifetime: i32 = 600,
+
+    super_bangs_per_level: u32 = 4,
+    // bullets added per frame while charging
+    super_bang_charge_rate: u32 = 10,
+    super_bang_min_bullets: u32 = 10,
+    super_bang_bullet_radius: f32 = 2.0,
+    super_bang_bullet_lifetime: i32 = 200,
+    super_bang_min_speed: f32 = 100.0,
+    super_bang_max_speed: f32 = 300.0,
+
+    enemies_per_level: u32 = 1,
+    debug_wave_size: u32 = 10,
+    enemy_min_radius: f32 = 10.0,
+    enemy_max_radius: f32 = 30.0,
+    enemy_min_speed: f32 = 20.0,
+    enemy_max_speed: f32 = 50.0,
+    enemy_min_spawn_distance: f32 = 300.0,
+    enemy_max_spawn_distance: f32 = 400.0,
+    // radius lost per hit, and the radius below which an enemy dies
+    enemy_damage: f32 = 5.0,
+    enemy_death_radius: f32 = 3.0,
+    // points per enemy destroyed, times the wave it is in
+    enemy_score: u32 = 100,
+
+    particle_speed: f32 = 500.0,
+    player_hit_particles: u32 = 10,
+    bullet_hit_particles: u32 = 80,
+    enemy_hit_particles: u32 = 50,
+    // most particles alive at once; bursts thin out as the limit nears
+    particle_budget: u32 = 4000,
+}
+
+impl GameConfig {
+    pub fn preset(difficulty: Difficulty) -> Self {
+        let normal = GameConfig::default();
+        match difficulty {
+            Difficulty::Easy => GameConfig {
+                super_bangs_per_level: 6,
+                ..normal
+            },
+            Difficulty::Normal => normal,
+            Difficulty::Hard => GameConfig {
+                enemies_per_level: 2,
+                super_bangs_per_level: 3,
+                ..normal
+            },
+            Difficulty::Insane => GameConfig {
+                enemies_per_level: 3,
+                super_bangs_per_level: 2,
+                ..normal
+            },
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if let Some(name) = self.non_finite() {
+            bail!("{} must be a finite number", name);
+        }
+        let ranges = [
+            ("enemy_min_radius", self.enemy_min_radius, self.enemy_max_radius),
+            ("enemy_min_speed", self.enemy_min_speed, self.enemy_max_speed),
+            ("enemy_min_spawn_distance", self.enemy_min_spawn_distance, self.enemy_max_spawn_distance),
+            ("super_bang_min_speed", self.super_bang_min_speed, self.super_bang_max_speed),
+        ];
+        for (name, min, max) in ranges {
+            if min > max {
+                bail!("{} must not be larger than its maximum", name);
+            }
+        }
+        let positive = [
+            ("player_radius", self.player_radius),
+            ("player_damage", self.player_damage),
+            ("player_speed", self.player_speed),
+            ("fire_cooldown", self.fire_cooldown),
+            ("bullet_speed", self.bullet_speed),
+            ("bullet_radius", self.bullet_radius),
+            ("bullet_lifetime", self.bullet_lifetime as f32),
+            ("super_bang_bullet_radius", self.super_bang_bullet_radius),
+            ("super_bang_bullet_lifetime", self.super_bang_bullet_lifetime as f32),
+            ("super_bang_min_speed", self.super_bang_min_speed),
+            ("enemy_min_radius", self.enemy_min_radius),
+            ("enemy_min_speed", self.enemy_min_speed),
+            ("enemy_min_spawn_distance", self.enemy_min_spawn_distance),
+            ("enemy_damage", self.enemy_damage),
+        ];
+        for (name, value) in positive {
+            if value <= 0.0 {
+                bail!("{} must be greater than zero", name);
+            }
+        }
+        if self.enemies_per_level == 0 {
+            bail!("enemies_per_level must be at least 1");
+        }
+        // the particle pool is allocated up front, and waves grow with the level
+        let limits = [
+            ("enemies_per_level", self.enemies_per_level, MAX_ENEMIES_PER_LEVEL),
+            ("debug_wave_size", self.debug_wave_size, MAX_DEBUG_WAVE),
+            ("particle_budget", self.particle_budget, MAX_PARTICLE_BUDGET),
+        ];
+        for (name, value, max) in limits {
+            if value > max {
+                bail!("{} must be at most {}", name, max);
+            }
+        }
+        // otherwise enemies die as they spawn, and every tick starts a new wave
+        if self.enemy_death_radius < 0.0 || self.enemy_death_radius >= self.enemy_min_radius {
+            bail!("enemy_death_radius must be at least zero and smaller than enemy_min_radius");
+        }
+        Ok(())
+    }
+
+    // The settings that differ from `base`, for storing a config compactly.
+    pub fn changes_from(&self, base: &GameConfig) -> Vec<(&'static str, String)> {
+        let base = base.values();
+        self.values()
+            .into_iter()
+            .zip(base)
+            .filter(|(value, base)| value != base)
+            .map(|(value, _)| value)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(key: &str, value: &str) -> Result<()> {
+        let mut config = GameConfig::default();
+        config.set(key, value)?;
+        config.validate()
+    }
+
+    #[test]
+    fn presets_are_valid() {
+        for difficulty in [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard, Difficulty::Insane] {
+            GameConfig::preset(difficulty).validate().unwrap();
+        }
+    }
+
+    #[test]
+    fn rejects_impossible_settings() {
+        let error = |key: &str, value: &str| check(key, value).err().unwrap().to_string();
+        assert_eq!(error("bullet_speed", "inf"), "bullet_speed must be a finite number");
+        assert_eq!(error("enemy_max_radius", "NaN"), "enemy_max_radius must be a finite number");
+        assert_eq!(error("bullet_radius", "0"), "bullet_radius must be greater than zero");
+        assert_eq!(error("enemy_min_speed", "80"), "enemy_min_speed must not be larger than its maximum");
+        assert_eq!(error("enemies_per_level", "0"), "enemies_per_level must be at least 1");
+        assert_eq!(error("particle_budget", "4000000000"), "particle_budget must be at most 100000");
+        assert!(check("particle_budget", "100000").is_ok());
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::VecDeque;
+
+use anyhow::{anyhow, bail, Result};
+use speedy2d::color::Color;
+use speedy2d::dimen::Vec2;
+use speedy2d::font::{Font, TextLayout, TextOptions};
+use speedy2d::shape::Rectangle;
+use speedy2d::window::VirtualKeyCode;
+
+use crate::canvas::Canvas;
+use crate::game::MAX_LEVEL;
+
+const MAX_LOG: usize = 200;
+const MAX_HISTORY: usize = 50;
+const MAX_INPUT: usize = 120;
+const MAX_COUNT: u32 = 1000; // enemies spawned or super bangs given at once
+
+const HEIGHT: f32 = 0.4; // of the screen
+const PADDING: f32 = 12.0;
+const TEXT_SIZE: f32 = 18.0;
+const LINE_HEIGHT: f32 = 22.0;
+
+const BACKGROUND: Color = Color::from_rgba(0.0, 0.0, 0.0, 0.8);
+const SEPARATOR: Color = Color::from_rgba(1.0, 1.0, 1.0, 0.3);
+const ECHO_COLOR: Color = Color::from_rgba(1.0, 1.0, 1.0, 0.5);
+const ERROR_COLOR: Color = Color::from_rgb(1.0, 0.4, 0.4);
+
+pub const HELP: &str = "\
+help                     this list
+spawn [enemy] <n>        spawn n enemies around the player
+level <n>                clear the arena and start wave n
+god                      turn invulnerability on or off
+give bangs <n>           add n super bangs
+timescale <x>            run the game x times as fast
+seed [n]                 show the seed, or start over with a new one
+kill all                 destroy every enemy
+set <key> [value]        show or change a game setting, as in the config file's [game] section";
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ConsoleCommand {
+    Help,
+    Spawn(u32),
+    Level(u32),
+    God,
+    GiveBangs(u32),
+    TimeScale(f32),
+    Seed(Option<u64>),
+    KillAll,
+    Set(String, Option<String>),
+}
+
+fn number<T: std::str::FromStr>(value: Option<&str>, what: &str) -> Result<T> {
+    let value = value.ok_or_else(|| anyhow!("expected a {}", what))?;
+    value.parse().map_err(|_| anyhow!("invalid {} `{}`", what, value))
+}
+
+// Counts are capped so a typo can't overflow the game's counters or spawn
+// enemies until memory runs out.
+fn count(value: Option<&str>, what: &str, max: u32) -> Result<u32> {
+    let count = number(value, what)?;
+    if count > max {
+        bail!("the {} can be at most {}", what, max);
+    }
+    Ok(count)
+}
+
+impl ConsoleCommand {
+    pub fn parse(line: &str) -> Result<Self> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let arg = |index: usize| words.get(index).copied();
+        let command = match words.as_slice() {
+            ["help", ..] => ConsoleCommand::Help,
+            // there is only one kind of enemy so far
+            ["spawn", "enemy", ..] => ConsoleCommand::Spawn(count(arg(2), "count", MAX_COUNT)?),
+            ["spawn", ..] => ConsoleCommand::Spawn(count(arg(1), "count", MAX_COUNT)?),
+            ["level", ..] => ConsoleCommand::Level(count(arg(1), "wave", MAX_LEVEL)?),
+            ["god", ..] => ConsoleCommand::God,
+            ["give", "bangs", ..] => ConsoleCommand::GiveBangs(count(arg(2), "count", MAX_COUNT)?),
+            ["timescale", ..] => {
+                let scale: f32 = number(arg(1), "scale")?;
+                if !(scale > 0.0 && scale <= 10.0) {
+                    bail!("the time scale has to be above 0 and at most 10");
+                }
+                ConsoleCommand::TimeScale(scale)
+            }
+            ["seed", ..] => match arg(1) {
+                Some(seed) => ConsoleCommand::Seed(Some(number(Some(seed), "seed")?)),
+                None => ConsoleCommand::Seed(None),
+            },
+            ["kill", "all", ..] => ConsoleCommand::KillAll,
+            ["kill", ..] => bail!("expected `kill all`"),
+            ["set", ..] => {
+                let key = arg(1).ok_or_else(|| anyhow!("expected a setting"))?;
+                ConsoleCommand::Set(key.to_string(), arg(2).map(str::to_string))
+            }
+            [] => bail!("type `help` for a list of commands"),
+            [name, ..] => bail!("unknown command `{}`, type `help` for a list", name),
+        };
+        if words.len() > command.max_words() {
+            bail!("too many arguments for `{}`", words[0]);
+        }
+        Ok(command)
+    }
+
+    fn max_words(&self) -> usize {
+        match self {
+            Self::Help | Self::God => 1,
+            Self::Level(_) | Self::TimeScale(_) | Self::Seed(_) | Self::KillAll => 2,
+            Self::Spawn(_) | Self::GiveBangs(_) | Self::Set(..) => 3,
+        }
+    }
+
+    // Whether the command changes the game, which isn't allowed while
+    // recording or replaying: the recording would no longer match.
+    pub fn is_cheat(&self) -> bool {
+        !matches!(self, Self::Help | Self::Seed(None) | Self::Set(_, None))
+    }
+}
+
+// A command line over the top of the screen, opened with the backtick key.
+// Commands are run by the window, which knows about more than the game.
+#[derive(Default)]
+pub struct Console {
+    pub open: bool,
+    input: String,
+    history: Vec<String>,
+    // the entry being edited when going through the history
+    browsing: Option<usize>,
+    log: VecDeque<(String, Color)>,
+}
+
+impl Console {
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+    }
+
+    pub fn print(&mut self, text: &str, color: Color) {
+        for line in text.lines() {
+            if self.log.len() == MAX_LOG {
+                self.log.pop_front();
+            }
+            self.log.push_back((line.to_string(), color));
+        }
+    }
+
+    pub fn error(&mut self, err: &anyhow::Error) {
+        self.print(&format!("{:#}", err), ERROR_COLOR);
+    }
+
+    pub fn char_typed(&mut self, c: char) {
+        if !c.is_control() && c != '`' && self.input.chars().count() < MAX_INPUT {
+            self.input.push(c);
+        }
+    }
+
+    // Returns the command entered with Return, once it parses.
+    pub fn key_down(&mut self, key: VirtualKeyCode) -> Option<ConsoleCommand> {
+        match key {
+            VirtualKeyCode::Escape => self.open = false,
+            VirtualKeyCode::Backspace => {
+                self.input.pop();
+            }
+            VirtualKeyCode::Up => self.browse(-1),
+            VirtualKeyCode::Down => self.browse(1),
+            VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => return self.submit(),
+            _ => {}
+        }
+        None
+    }
+
+    fn browse(&mut self, step: isize) {
+        let Some(last) = self.history.len().checked_sub(1) else {
+            return;
+        };
+        let index = match self.browsing {
+            Some(index) => index as isize + step,
+            None if step < 0 => last as isize,
+            None => return,
+        };
+        if index > last as isize {
+            self.browsing = None;
+            self.input.clear();
+        } else {
+            let index = index.max(0) as usize;
+            self.browsing = Some(index);
+            self.input = self.history[index].clone();
+        }
+    }
+
+    fn submit(&mut self) -> Option<ConsoleCommand> {
+        let line = std::mem::take(&mut self.input);
+        self.browsing = None;
+        let line = line.trim();
+        if line.is_empty() {
+            return None;
+        }
+        self.print(&format!("> {}", line), ECHO_COLOR);
+        if self.history.last().map(String::as_str) != Some(line) {
+            if self.history.len() == MAX_HISTORY {
+                self.history.remove(0);
+            }
+            self.history.push(line.to_string());
+        }
+        match ConsoleCommand::parse(line) {
+            Ok(command) => Some(command),
+            Err(err) => {
+                self.error(&err);
+                None
+            }
+        }
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas, font: &Font, hud_size: Vec2) {
+        let height = (hud_size.y * HEIGHT).round();
+        canvas.draw_rectangle(&Rectangle::new(Vec2::ZERO, Vec2::new(hud_size.x, height)), BACKGROUND);
+        let prompt_y = height - PADDING - LINE_HEIGHT;
+        canvas.draw_line(
+            Vec2::new(0.0, prompt_y - PADDING / 2.0),
+            Vec2::new(hud_size.x, prompt_y - PADDING / 2.0),
+            1.0,
+            SEPARATOR,
+        );
+
+        let mut draw_line = |text: &str, y: f32, color: Color| {
+            let layout = font.layout_text(text, canvas.font_size(TEXT_SIZE), TextOptions::new());
+            canvas.draw_text(Vec2::new(PADDING, y), color, &layout);
+        };
+        // the newest lines, from the bottom up, as far as they fit
+        let mut y = prompt_y - PADDING - LINE_HEIGHT;
+        for (text, color) in self.log.iter().rev() {
+            if y < PADDING {
+                break;
+            }
+            draw_line(text, y, *color);
+            y -= LINE_HEIGHT;
+        }
+        draw_line(&format!("> {}_", self.input), prompt_y, Color::WHITE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(line: &str) -> String {
+        ConsoleCommand::parse(line).err().unwrap().to_string()
+    }
+
+    #[test]
+    fn parses_commands() {
+        let parse = |line: &str| ConsoleCommand::parse(line).unwrap();
+        assert_eq!(parse("help"), ConsoleCommand::Help);
+        assert_eq!(parse("spawn 5"), ConsoleCommand::Spawn(5));
+        assert_eq!(parse("  spawn   enemy 3 "), ConsoleCommand::Spawn(3));
+        assert_eq!(parse("level 4"), ConsoleCommand::Level(4));
+        assert_eq!(parse("give bangs 2"), ConsoleCommand::GiveBangs(2));
+        assert_eq!(parse("timescale 0.5"), ConsoleCommand::TimeScale(0.5));
+        assert_eq!(parse("seed"), ConsoleCommand::Seed(None));
+        assert_eq!(parse("seed 42"), ConsoleCommand::Seed(Some(42)));
+        assert_eq!(parse("kill all"), ConsoleCommand::KillAll);
+        assert_eq!(parse("set bullet_speed"), ConsoleCommand::Set("bullet_speed".to_string(), None));
+        assert_eq!(
+            parse("set bullet_speed 300"),
+            ConsoleCommand::Set("bullet_speed".to_string(), Some("300".to_string()))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert_eq!(error("spawn"), "expected a count");
+        assert_eq!(error("spawn lots"), "invalid count `lots`");
+        assert_eq!(error("spawn 4294967295"), "the count can be at most 1000");
+        assert_eq!(error("give bangs 1001"), "the count can be at most 1000");
+        assert_eq!(error("level 4000000000"), "the wave can be at most 1000");
+        assert_eq!(error("timescale 0"), "the time scale has to be above 0 and at most 10");
+        assert_eq!(error("timescale NaN"), "the time scale has to be above 0 and at most 10");
+        assert_eq!(error("kill everyone"), "expected `kill all`");
+        assert_eq!(error("god mode"), "too many arguments for `god`");
+        assert_eq!(error("fly"), "unknown command `fly`, type `help` for a list");
+        assert_eq!(error(""), "type `help` for a list of commands");
+    }
+
+    #[test]
+    fn only_looking_is_allowed_while_recording() {
+        assert!(!ConsoleCommand::Help.is_cheat());
+        assert!(!ConsoleCommand::Seed(None).is_cheat());
+        assert!(!ConsoleCommand::Set("bullet_speed".to_string(), None).is_cheat());
+        assert!(ConsoleCommand::Seed(Some(1)).is_cheat());
+        assert!(ConsoleCommand::Set("bullet_speed".to_string(), Some("1".to_string())).is_cheat());
+        assert!(ConsoleCommand::KillAll.is_cheat());
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::VecDeque;
+
+use speedy2d::color::Color;
+use speedy2d::dimen::Vec2;
+use speedy2d::font::{Font, TextLayout, TextOptions};
+use speedy2d::shape::Rectangle;
+
+use crate::canvas::Canvas;
+use crate::game::Game;
+use crate::game_entity::GameEntity;
+use crate::{COL_BULLET, COL_ENEMY, COL_PLAYER};
+
+const CIRCLE_SEGMENTS: usize = 20;
+const VELOCITY_SCALE: f32 = 0.25; // velocity lines show where things are this many seconds on
+const VELOCITY_COLOR: Color = Color::from_rgba(1.0, 1.0, 0.3, 0.8);
+const GRID_COLOR: Color = Color::from_rgba(1.0, 1.0, 1.0, 0.08);
+const CELL_COLOR: Color = Color::from_rgba(0.3, 0.6, 1.0, 0.06); // per circle in the cell
+
+const MARGIN: f32 = 20.0;
+const PADDING: f32 = 10.0;
+const PANEL_WIDTH: f32 = 380.0;
+const TEXT_SIZE: f32 = 16.0;
+const LINE_HEIGHT: f32 = 20.0;
+const GRAPH_HEIGHT: f32 = 60.0;
+const GRAPH_MAX: f32 = 1.0 / 20.0; // frame time at the top of the graph
+const TARGET_FRAME: f32 = 1.0 / 60.0;
+const PANEL_BACKGROUND: Color = Color::from_rgba(0.0, 0.0, 0.0, 0.7);
+
+// Red for enemies, green for the player and blue for bullets, mixed for
+// anything on more than one layer.
+fn layer_color(layer: u8) -> Color {
+    let on = |bit: u8| if layer & bit != 0 { 1.0 } else { 0.0 };
+    Color::from_rgb(on(COL_ENEMY), on(COL_PLAYER), on(COL_BULLET))
+}
+
+fn draw_ring(canvas: &mut Canvas, pos: Vec2, radius: f32, color: Color) {
+    let point = |index: usize| {
+        let angle = index as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+        pos + Vec2::new(angle.cos(), angle.sin()) * radius
+    };
+    for index in 0..CIRCLE_SEGMENTS {
+        canvas.draw_line(point(index), point(index + 1), 1.0, color);
+    }
+}
+
+fn draw_collider(canvas: &mut Canvas, entity: &impl GameEntity, vel: Vec2) {
+    let info = entity.collider_info();
+    draw_ring(canvas, *info.pos, *info.radius, layer_color(*info.layer));
+    canvas.draw_line(*info.pos, *info.pos + vel * VELOCITY_SCALE, 1.0, VELOCITY_COLOR);
+}
+
+// The broad-phase grid, the collision circles and the velocities, in world
+// coordinates. Cells are shaded by how many circles are in them.
+pub fn draw_world(canvas: &mut Canvas, game: &Game) {
+    let cell_size = game.enemy_grid.cell_size;
+    if cell_size > 0.0 {
+        let arena = game.arena_size;
+        let mut x = 0.0;
+        while x <= arena.x {
+            canvas.draw_line(Vec2::new(x, 0.0), Vec2::new(x, arena.y), 1.0, GRID_COLOR);
+            x += cell_size;
+        }
+        let mut y = 0.0;
+        while y <= arena.y {
+            canvas.draw_line(Vec2::new(0.0, y), Vec2::new(arena.x, y), 1.0, GRID_COLOR);
+            y += cell_size;
+        }
+        for ((x, y), count) in game.enemy_grid.occupied().chain(game.bullet_grid.occupied()) {
+            let top_left = Vec2::new(x as f32, y as f32) * cell_size;
+            let alpha = (CELL_COLOR.a() * count as f32).min(0.5);
+            let color = Color::from_rgba(CELL_COLOR.r(), CELL_COLOR.g(), CELL_COLOR.b(), alpha);
+            canvas.draw_rectangle(&Rectangle::new(top_left, top_left + Vec2::new(cell_size, cell_size)), color);
+        }
+    }
+
+    draw_collider(canvas, &game.player, game.player.vel);
+    for enemy in &game.enemies {
+        draw_collider(canvas, enemy, enemy.vel);
+    }
+    for bullet in &game.bullets {
+        draw_collider(canvas, bullet, bullet.vel);
+    }
+}
+
+// Frame rate, a graph of recent frame times and the counts behind them, in
+// the bottom left corner of the HUD.
+pub fn draw_panel(canvas: &mut Canvas, font: &Font, hud_size: Vec2, game: &Game, frame_times: &VecDeque<f32>) {
+    let count = frame_times.len().max(1) as f32;
+    let average = frame_times.iter().sum::<f32>() / count;
+    let worst = frame_times.iter().copied().fold(0.0, f32::max);
+    let enemies = game.enemies.len() as u64;
+    let bullets = game.bullets.len() as u64;
+    // the player against every enemy, every enemy against every bullet, and
+    // every pair of enemies
+    let all_pairs = enemies + enemies * bullets + enemies * enemies.saturating_sub(1) / 2;
+    let cells = game.enemy_grid.occupied().count() + game.bullet_grid.occupied().count();
+    let lines = [
+        format!(
+            "{:.0} fps, {:.1} ms avg, {:.1} ms max",
+            1.0 / average.max(0.0001),
+            average * 1000.0,
+            worst * 1000.0
+        ),
+        format!("enemies {}  bullets {}  particles {}", enemies, bullets, game.particles.len()),
+        format!("collision checks {} of {} pairs", game.collision_checks, all_pairs),
+        format!("grid cells {:.0} units, {} in use", game.enemy_grid.cell_size, cells),
+    ];
+
+    let height = PADDING * 3.0 + GRAPH_HEIGHT + LINE_HEIGHT * lines.len() as f32;
+    let top_left = Vec2::new(MARGIN, hud_size.y - MARGIN - height);
+    canvas.draw_rectangle(
+        &Rectangle::new(top_left, top_left + Vec2::new(PANEL_WIDTH, height)),
+        PANEL_BACKGROUND,
+    );
+
+    let mut y = top_left.y + PADDING;
+    for line in &lines {
+        let text = font.layout_text(line, canvas.font_size(TEXT_SIZE), TextOptions::new());
+        canvas.draw_text(Vec2::new(top_left.x + PADDING, y), Color::WHITE, &text);
+        y += LINE_HEIGHT;
+    }
+
+    // one bar per frame, oldest on the left, with a line at 60 fps
+    let graph_left = top_left.x + PADDING;
+    let graph_bottom = y + PADDING + GRAPH_HEIGHT;
+    let graph_width = PANEL_WIDTH - PADDING * 2.0;
+    let bar_width = graph_width / crate::FRAME_TIME_HISTORY as f32;
+    for (index, time) in frame_times.iter().enumerate() {
+        let bar_height = (time / GRAPH_MAX).min(1.0) * GRAPH_HEIGHT;
+        let color = if *time <= TARGET_FRAME * 1.1 {
+            Color::GREEN
+        } else if *time <= TARGET_FRAME * 2.0 {
+            Color::YELLOW
+        } else {
+            Color::RED
+        };
+        let left = graph_left + index as f32 * bar_width;
+        canvas.draw_rectangle(
+            &Rectangle::new(
+                Vec2::new(left, graph_bottom - bar_height),
+                Vec2::new(left + bar_width.max(1.0), graph_bottom),
+            ),
+            color,
+        );
+    }
+    let target_y = graph_bottom - TARGET_FRAME / GRAPH_MAX * GRAPH_HEIGHT;
+    canvas.draw_line(
+        Vec2::new(graph_left, target_y),
+        Vec2::new(graph_left + graph_width, target_y),
+        1.0,
+        Color::from_rgba(1.0, 1.0, 1.0, 0.5),
+    );
+}
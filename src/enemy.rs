@@ -1,42 +1,56 @@
 use std::f32::consts::TAU;
-use rand::random;
+use crate::rng::random;
 use speedy2d::color::Color;
 use speedy2d::dimen::Vec2;
-use speedy2d::Graphics2D;
-use crate::game_entity::{ColliderInfo, GameEntity, impulse};
-use crate::{COL_BULLET, COL_ENEMY, COL_PLAYER, HEIGHT, WIDTH};
+use crate::canvas::Canvas;
+use crate::config::GameConfig;
+use crate::trail::Trail;
+use crate::game_entity::{ColliderInfo, GameEntity, impulse, next_id};
+use crate::palette::{EnemyStyle, Mark};
+use crate::{COL_BULLET, COL_ENEMY, COL_PLAYER};
 
 #[derive(Clone)]
 pub struct Enemy {
+    pub id: u32,
     pub pos: Vec2,
     pub vel: Vec2,
     pub radius: f32,
     pub color: Color,
+    // the random color it spawned with, which `color` is picked by
+    pub tone: Color,
+    pub mark: Option<Mark>,
     pub layer: u8,
     pub mask: u8,
+    pub trail: Trail,
+    pub damage: f32,
+    pub death_radius: f32,
 }
 
 impl GameEntity for Enemy {
-    fn draw(&self, graphics: &mut Graphics2D) {
-        graphics.draw_circle((self.pos.x, self.pos.y), self.radius, self.color);
+    fn draw(&self, canvas: &mut Canvas) {
+        canvas.draw_circle(self.pos, self.radius, self.color);
+        if let Some(mark) = self.mark {
+            draw_mark(canvas, mark, self.pos, self.radius, self.color);
+        }
     }
 
-    fn update(&mut self, dt: f32) -> bool {
-        self.pos = self.pos + self.vel * dt;
+    fn update(&mut self, dt: f32, arena_size: Vec2) -> bool {
+        self.pos += self.vel * dt;
 
         if (self.pos.x + self.radius) < 0.0 {
-            self.pos.x = WIDTH - self.radius;
-        } else if (self.pos.x - self.radius) > WIDTH {
+            self.pos.x = arena_size.x - self.radius;
+        } else if (self.pos.x - self.radius) > arena_size.x {
             self.pos.x = self.radius;
         }
 
         if (self.pos.y + self.radius) < 0.0 {
-            self.pos.y = HEIGHT - self.radius;
-        } else if (self.pos.y - self.radius) > HEIGHT {
+            self.pos.y = arena_size.y - self.radius;
+        } else if (self.pos.y - self.radius) > arena_size.y {
             self.pos.y = 0.0 + self.radius;
         }
+        self.trail.push(self.pos);
 
-        self.radius > 3.0
+        self.radius > self.death_radius
     }
 
     fn collider_info(&self) -> ColliderInfo<'_> {
@@ -49,34 +63,83 @@ impl GameEntity for Enemy {
     }
 
     fn deal_damage(&mut self, other_vel: &Vec2, other_mass: f32) {
-        self.radius = (self.radius - 5.0).max(0.0);
+        self.radius = (self.radius - self.damage).max(0.0);
         self.vel = impulse(&self.vel, self.radius, other_vel, other_mass);
     }
 }
 
 impl Enemy {
-    pub fn spawn(target: &Vec2) -> Self {
+    pub fn spawn(target: &Vec2, config: &GameConfig, style: &EnemyStyle) -> Self {
+        let between = |min: f32, max: f32| random::<f32>() * (max - min) + min;
         let angle = random::<f32>() * TAU;
-        let dist = (random::<f32>() * 100.0) + 300.0;
-        let radius = random::<f32>() * 20.0 + 10.0;
-        let color = Color::from_rgb(random(), random(), random());
+        let dist = between(config.enemy_min_spawn_distance, config.enemy_max_spawn_distance);
+        let radius = between(config.enemy_min_radius, config.enemy_max_radius);
+        let tone = Color::from_rgb(random(), random(), random());
+        let (color, mark) = style.look(tone);
 
         let pos = Vec2::new(target.x + angle.cos() * dist, target.y + angle.sin() * dist);
-        let vel: Vec2 = (target - pos).normalize().unwrap() * (random::<f32>() * 30.0 + 20.0);
+        // straight back along the angle if the spawn distance is zero
+        let direction = (target - pos).normalize().unwrap_or(Vec2::new(-angle.cos(), -angle.sin()));
+        let vel: Vec2 = direction * between(config.enemy_min_speed, config.enemy_max_speed);
 
         Enemy {
+            id: next_id(),
             pos,
             vel,
             radius,
             color,
+            tone,
+            mark,
             layer: COL_ENEMY,
             mask: COL_PLAYER | COL_BULLET | COL_ENEMY,
+            damage: config.enemy_damage,
+            death_radius: config.enemy_death_radius,
+            trail: Trail::default(),
         }
     }
 
-    pub fn spawn_n(enemies: &mut Vec<Enemy>, num: u32, target: &Vec2) {
+    pub fn spawn_n(enemies: &mut Vec<Enemy>, num: u32, target: &Vec2, config: &GameConfig, style: &EnemyStyle) {
         for _ in 0..num {
-            enemies.push(Enemy::spawn(target));
+            enemies.push(Enemy::spawn(target, config, style));
+        }
+    }
+
+    pub fn restyle(&mut self, style: &EnemyStyle) {
+        (self.color, self.mark) = style.look(self.tone);
+    }
+}
+
+// Marks are cut out of the enemy in the background color.
+fn draw_mark(canvas: &mut Canvas, mark: Mark, pos: Vec2, radius: f32, color: Color) {
+    let size = radius * 0.5;
+    let thickness = (radius * 0.15).max(1.0);
+    let outline = |canvas: &mut Canvas, corners: &[Vec2]| {
+        for (index, corner) in corners.iter().enumerate() {
+            let next = corners[(index + 1) % corners.len()];
+            canvas.draw_line(pos + *corner, pos + next, thickness, Color::BLACK);
+        }
+    };
+    match mark {
+        Mark::Dot => canvas.draw_circle(pos, size * 0.6, Color::BLACK),
+        Mark::Ring => {
+            canvas.draw_circle(pos, size, Color::BLACK);
+            canvas.draw_circle(pos, size - thickness, color);
+        }
+        Mark::Bar => canvas.draw_line(pos - Vec2::new(size, 0.0), pos + Vec2::new(size, 0.0), thickness, Color::BLACK),
+        Mark::Cross => {
+            canvas.draw_line(pos - Vec2::new(size, 0.0), pos + Vec2::new(size, 0.0), thickness, Color::BLACK);
+            canvas.draw_line(pos - Vec2::new(0.0, size), pos + Vec2::new(0.0, size), thickness, Color::BLACK);
+        }
+        Mark::Triangle => {
+            let corner = |angle: f32| Vec2::new(angle.cos(), angle.sin()) * size;
+            outline(canvas, &[corner(-TAU / 4.0), corner(TAU / 12.0), corner(TAU * 5.0 / 12.0)]);
+        }
+        Mark::Square => {
+            let half = size * 0.75;
+            outline(
+                canvas,
+                &[Vec2::new(-half, -half), Vec2::new(half, -half), Vec2::new(half, half), Vec2::new(-half, half)],
+            );
         }
     }
 }
\ No newline at end of file
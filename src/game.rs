@@ -0,0 +1,430 @@
+use speedy2d::color::Color;
+use speedy2d::dimen::Vec2;
+
+use crate::bullet::Bullet;
+use crate::cli::Difficulty;
+use crate::config::GameConfig;
+use crate::enemy::Enemy;
+use crate::game_entity::{collide, GameEntity};
+use crate::grid::Grid;
+use crate::input::Action;
+use crate::palette::EnemyStyle;
+use crate::particle::{Emitter, EmitterId, EmitterInstance, ParticlePool};
+use crate::player::Player;
+use crate::rng;
+use crate::sound::{SoundPlayer, SoundType};
+
+// enemy count and level at which the music reaches full intensity
+const INTENSE_CROWD: f32 = 20.0;
+const INTENSE_LEVEL: f32 = 10.0;
+
+// grid cells are as big as the largest enemy, but no smaller than this
+const MIN_CELL_SIZE: f32 = 32.0;
+
+// the highest level a game can start at, which keeps the enemy and super bang
+// counts of a wave reasonable
+pub const MAX_LEVEL: u32 = 1000;
+
+// The particle effects of the game, registered with its pool.
+struct Effects {
+    player_hit: EmitterId,
+    bullet_hit: EmitterId,
+    enemy_hit: EmitterId,
+    charge: EmitterInstance,
+}
+
+impl Effects {
+    fn definitions(config: &GameConfig) -> [Emitter; 4] {
+        [
+            Emitter::explosion(config.player_hit_particles, config.particle_speed),
+            Emitter::explosion(config.bullet_hit_particles, config.particle_speed),
+            Emitter::explosion(config.enemy_hit_particles, config.particle_speed),
+            Emitter::charge(),
+        ]
+    }
+
+    fn new(config: &GameConfig) -> (ParticlePool, Effects) {
+        let mut particles = ParticlePool::new(config.particle_budget as usize);
+        let [player_hit, bullet_hit, enemy_hit, charge] =
+            Self::definitions(config).map(|emitter| particles.register(emitter));
+        let effects = Effects {
+            player_hit,
+            bullet_hit,
+            enemy_hit,
+            charge: EmitterInstance::new(charge, Vec2::ZERO, Color::WHITE),
+        };
+        (particles, effects)
+    }
+
+    fn reconfigure(&self, particles: &mut ParticlePool, config: &GameConfig) {
+        let ids = [self.player_hit, self.bullet_hit, self.enemy_hit, self.charge.emitter];
+        for (id, emitter) in ids.into_iter().zip(Self::definitions(config)) {
+            particles.replace(id, emitter);
+        }
+    }
+}
+
+// Things that happened during the last tick, for effects that live outside
+// the simulation such as the camera.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GameEvent {
+    Explosion(Vec2),
+    PlayerHit(Vec2),
+    SuperBang(Vec2),
+}
+
+// The simulated world. It knows nothing about windows or fonts, so the same
+// code runs interactively, from a replay, or headless.
+pub struct Game {
+    pub sound: SoundPlayer,
+    pub seed: u64,
+    pub difficulty: Difficulty,
+    pub config: GameConfig,
+    pub arena_size: Vec2,
+    pub enemy_style: EnemyStyle,
+
+    pub mouse_pos: Vec2,
+    pub paused: bool,
+    pub cursor_visible: bool,
+
+    pub level: u32,
+    pub super_bang: u32,
+    pub charged_super_bang: u32,
+    pub charging: bool,
+    release_super_bang: bool,
+
+    pub bullets_fired: u32,
+    pub bullets_hit: u32,
+    pub score: u64,
+
+    pub firing: bool,
+    pub firing_cooldown: f32,
+    // the player takes no damage; for testing from the console
+    pub god: bool,
+    // a console cheat was used, so the score can't go in the high scores
+    pub cheated: bool,
+
+    pub player: Player,
+    pub enemies: Vec<Enemy>,
+    pub bullets: Vec<Bullet>,
+    pub particles: ParticlePool,
+    effects: Effects,
+    pub events: Vec<GameEvent>,
+
+    // the broad phase of the last tick, kept for the debug overlay
+    pub enemy_grid: Grid,
+    pub bullet_grid: Grid,
+    pub collision_checks: u64,
+}
+
+impl Game {
+    pub fn new(
+        seed: u64,
+        level: u32,
+        difficulty: Difficulty,
+        config: GameConfig,
+        arena_size: Vec2,
+        sound: SoundPlayer,
+    ) -> Self {
+        rng::seed(seed);
+        let (particles, effects) = Effects::new(&config);
+
+        Game {
+            sound,
+            seed,
+            difficulty,
+            config,
+            arena_size,
+            enemy_style: EnemyStyle::default(),
+
+            mouse_pos: Vec2::ZERO,
+            paused: false,
+            cursor_visible: true,
+
+            level,
+            super_bang: 0,
+            charged_super_bang: 0,
+            charging: false,
+            release_super_bang: false,
+
+            bullets_fired: 0,
+            bullets_hit: 0,
+            score: 0,
+
+            firing: false,
+            firing_cooldown: 0.0,
+            god: false,
+            cheated: false,
+
+            player: Player::new(arena_size / 2.0, &config),
+            enemies: Vec::new(),
+            bullets: Vec::new(),
+            particles,
+            effects,
+            events: Vec::new(),
+
+            enemy_grid: Grid::default(),
+            bullet_grid: Grid::default(),
+            collision_checks: 0,
+        }
+    }
+
+    pub fn handle_action(&mut self, action: Action) {
+        match action {
+            Action::Aim(pos) => self.mouse_pos = pos,
+            Action::FireStart => self.firing = true,
+            Action::FireStop => {
+                self.firing = false;
+                self.firing_cooldown = 0.0;
+            }
+            Action::ChargeStart => self.charging = true,
+            Action::ChargeRelease => {
+                self.charging = false;
+                self.release_super_bang = true;
+            }
+            Action::RotateLeft => self.player.rot = -self.config.player_turn_speed,
+            Action::RotateRight => self.player.rot = self.config.player_turn_speed,
+            Action::RotateStop => self.player.rot = 0.0,
+            Action::MoveForward => self.player.speed = self.config.player_speed, // move forward
+            Action::MoveBackward => self.player.speed = -self.config.player_speed, // move backward
+            Action::TogglePause => self.paused = !self.paused,
+            Action::ToggleCursor => self.cursor_visible = !self.cursor_visible,
+            Action::SpawnDebugWave => {
+                self.sound.wave_started();
+                self.spawn_enemies(self.config.debug_wave_size);
+            }
+        }
+    }
+
+    pub fn tick(&mut self, dt: f32) {
+        self.events.clear();
+
+        if self.release_super_bang {
+            self.release_super_bang = false;
+            self.sound.play(SoundType::MultiFire, None);
+
+            let num_bullets = std::cmp::max(self.config.super_bang_min_bullets, self.charged_super_bang);
+            Bullet::super_bang(&mut self.bullets, num_bullets, self.player.pos, &self.config);
+            self.charged_super_bang = 0;
+            self.bullets_fired += num_bullets;
+            self.events.push(GameEvent::SuperBang(self.player.pos));
+        }
+
+        // spawn new wave of enemies if there are none
+        if self.enemies.is_empty() {
+            self.sound.wave_started();
+            Enemy::spawn_n(
+                &mut self.enemies,
+                self.config.enemies_per_level.saturating_mul(self.level),
+                &self.player.pos,
+                &self.config,
+                &self.enemy_style,
+            );
+            self.super_bang = self
+                .super_bang
+                .saturating_add(self.config.super_bangs_per_level.saturating_mul(self.level));
+            self.sound.play(SoundType::Pickup, None);
+            self.level += 1;
+        }
+
+        // charge super bang
+        if self.charging && self.super_bang > 0 {
+            self.super_bang -= 1;
+            self.charged_super_bang += self.config.super_bang_charge_rate;
+            self.sound.play(SoundType::Load, None);
+        }
+
+        // fire bullets
+        if self.firing {
+            if self.firing_cooldown > 0.0 {  // prevent firing while cooling down
+                self.firing_cooldown -= dt;
+            } else {
+                self.firing_cooldown += self.config.fire_cooldown; // update cooldown for firing
+                self.sound.play(SoundType::Fire, None);
+
+                // use self.player.angle to calculate the bullet velocity
+                let (sin, cos) = self.player.angle.sin_cos();
+                let vel = Vec2::new(cos, sin) * self.config.bullet_speed;
+
+                self.bullets.push(Bullet::new(self.player.pos, vel, &self.config));
+                self.bullets_fired += 1;
+            }
+        }
+
+        if self.cursor_visible {
+            let dir = self.mouse_pos - self.player.pos;
+            let mut angle = dir.y.atan2(dir.x);
+            if angle < 0.0 {
+                angle += std::f32::consts::TAU; // TAU = 2*PI
+            }
+            self.player.angle = angle;
+        } else {
+            self.player.angle += self.player.rot * dt;
+            if self.player.angle < 0.0 {
+                self.player.angle += std::f32::consts::TAU; // TAU = 2*PI
+            } else if self.player.angle > std::f32::consts::TAU {
+                self.player.angle -= std::f32::consts::TAU; // TAU = 2*PI
+            }
+        }
+
+        if !self.paused {
+            self.update(dt);
+        }
+
+        self.sound.listener = self.player.pos;
+    }
+
+    // Starts over, keeping the tuning, the enemy style and the sound player.
+    pub fn restart(&mut self, seed: u64, level: u32) {
+        let sound = std::mem::replace(&mut self.sound, SoundPlayer::null());
+        let enemy_style = self.enemy_style;
+        *self = Game::new(seed, level, self.difficulty, self.config, self.arena_size, sound);
+        self.enemy_style = enemy_style;
+    }
+
+    // The player's size is their health.
+    pub fn is_over(&self) -> bool {
+        self.player.radius <= 0.0
+    }
+
+    // Switches to new tuning mid-game, as when the config file is edited.
+    pub fn set_config(&mut self, config: GameConfig) {
+        if config.particle_budget != self.config.particle_budget {
+            (self.particles, self.effects) = Effects::new(&config);
+        } else {
+            self.effects.reconfigure(&mut self.particles, &config);
+        }
+        self.config = config;
+        self.player.drag = config.player_drag;
+        self.player.damage = config.player_damage;
+    }
+
+    // Recolors the enemies already in the arena too.
+    pub fn set_enemy_style(&mut self, style: EnemyStyle) {
+        self.enemy_style = style;
+        for enemy in &mut self.enemies {
+            enemy.restyle(&style);
+        }
+    }
+
+    pub fn spawn_enemies(&mut self, count: u32) {
+        Enemy::spawn_n(&mut self.enemies, count, &self.player.pos, &self.config, &self.enemy_style);
+    }
+
+    // Blows up every enemy without scoring them, which starts the next wave.
+    pub fn kill_all(&mut self) {
+        for enemy in self.enemies.drain(..) {
+            self.events.push(GameEvent::Explosion(enemy.pos));
+            self.particles.burst(self.effects.enemy_hit, enemy.pos, enemy.color);
+        }
+        self.sound.play(SoundType::Explode, None);
+    }
+
+    // Clears the arena so the next tick starts the given wave.
+    pub fn start_wave(&mut self, wave: u32) {
+        self.enemies.clear();
+        self.bullets.clear();
+        self.level = wave.max(1);
+    }
+
+    // How hectic the game is, from 0 to 1: a big crowd or a late wave.
+    pub fn intensity(&self) -> f32 {
+        let crowd = self.enemies.len() as f32 / INTENSE_CROWD;
+        let wave = (self.level as f32 - 1.0) / INTENSE_LEVEL;
+        crowd.max(wave).min(1.0)
+    }
+
+    fn update(&mut self, dt: f32) {
+        let arena_size = self.arena_size;
+        self.player.update(dt, arena_size);
+
+        let alive = self.enemies.len();
+        self.enemies
+            .retain_mut(|enemy: &mut Enemy| enemy.update(dt, arena_size));
+        // the level has already moved on to the next wave
+        let destroyed = (alive - self.enemies.len()) as u64;
+        let points = destroyed * self.config.enemy_score as u64 * self.level.saturating_sub(1).max(1) as u64;
+        self.score = self.score.saturating_add(points);
+
+        // update bullets
+        self.bullets.retain_mut(|bullet: &mut Bullet| {
+            bullet.update(dt, arena_size)
+                && bullet.pos.x > 0.0
+                && bullet.pos.x < arena_size.x
+                && bullet.pos.y > 0.0
+                && bullet.pos.y < arena_size.y
+        });
+
+        self.particles.update(dt);
+        if self.charging && self.charged_super_bang > 0 {
+            self.effects.charge.pos = self.player.pos;
+            self.effects.charge.tint = self.player.color;
+            self.particles.emit(&mut self.effects.charge, dt);
+        }
+
+        // The broad phase: only circles sharing a grid cell can touch. The
+        // pairs that can are still checked in the order of checking them all,
+        // so the outcome is the same.
+        self.collision_checks = 0;
+        let cell_size = self.enemies.iter().fold(MIN_CELL_SIZE, |size, enemy| size.max(enemy.radius * 2.0));
+        self.enemy_grid.build(cell_size, self.enemies.iter().map(|enemy| (enemy.pos, enemy.radius)));
+        self.bullet_grid.build(cell_size, self.bullets.iter().map(|bullet| (bullet.pos, bullet.radius)));
+        let mut nearby = Vec::new();
+
+        // Enemy-player and enemy-bullet collisions
+        let mut bullet_alive = vec![true; self.bullets.len()];
+        for enemy in &mut self.enemies {
+            // Enemy-player collision
+            self.collision_checks += 1;
+            if collide(enemy, &self.player) {
+                self.sound.play(SoundType::PlayerHit, Some(enemy.pos));
+                enemy.deal_damage(&self.player.vel, self.player.radius);
+                if !self.god {
+                    self.player.deal_damage(&enemy.vel, enemy.radius);
+                }
+                self.events.push(GameEvent::PlayerHit(enemy.pos));
+                self.particles.burst(self.effects.player_hit, enemy.pos, self.player.color);
+            }
+
+            // Enemy-bullet collisions
+            self.bullet_grid.query(enemy.pos, enemy.radius, &mut nearby);
+            for &index in &nearby {
+                if !bullet_alive[index] {
+                    continue;
+                }
+                let bullet = &mut self.bullets[index];
+                self.collision_checks += 1;
+                if collide(enemy, bullet) {
+                    self.sound.play(SoundType::Explode, Some(enemy.pos));
+                    enemy.deal_damage(&bullet.vel, bullet.radius / 20.0);
+                    bullet.deal_damage(&enemy.vel, enemy.radius);
+                    self.events.push(GameEvent::Explosion(enemy.pos));
+                    self.particles.burst(self.effects.bullet_hit, enemy.pos, enemy.color);
+                    self.bullets_hit += 1;
+                    bullet_alive[index] = false;
+                }
+            }
+        }
+        let mut alive = bullet_alive.into_iter();
+        self.bullets.retain(|_| alive.next().unwrap_or(true));
+
+        // Enemy-enemy collisions
+        let mut pairs = Vec::new();
+        for (i, enemy) in self.enemies.iter().enumerate() {
+            self.enemy_grid.query(enemy.pos, enemy.radius, &mut nearby);
+            pairs.extend(nearby.iter().filter(|&&j| j > i).map(|&j| (i, j)));
+        }
+        for (i, j) in pairs {
+            let (head, tail) = self.enemies.split_at_mut(j);
+            let (e1, e2) = (&mut head[i], &mut tail[0]);
+            self.collision_checks += 1;
+            if collide(e1, e2) {
+                self.sound.play(SoundType::Explode, Some(e1.pos));
+                e1.deal_damage(&e2.vel, e2.radius);
+                e2.deal_damage(&e1.vel, e1.radius);
+                self.events.push(GameEvent::Explosion(e1.pos));
+                self.particles.burst(self.effects.enemy_hit, e1.pos, e1.color);
+            }
+        }
+    }
+}
@@ -1,5 +1,8 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
 use speedy2d::dimen::Vec2;
-use speedy2d::Graphics2D;
+
+use crate::canvas::Canvas;
 
 pub fn collide(e1: &impl GameEntity, e2: &impl GameEntity) -> bool {
     let c1 = e1.collider_info();
@@ -20,6 +23,13 @@ pub fn impulse(v1: &Vec2, m1: f32, v2: &Vec2, m2: f32) -> Vec2 {
 }
 
 
+// A number no other enemy or bullet has, so spectators can tell which one is
+// which from one snapshot to the next. Not part of the simulation.
+pub fn next_id() -> u32 {
+    static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
 pub struct ColliderInfo<'a> {
     pub mask: &'a u8,
     pub layer: &'a u8,
@@ -28,8 +38,8 @@ pub struct ColliderInfo<'a> {
 }
 
 pub trait GameEntity {
-    fn draw(&self, graphics: &mut Graphics2D);
-    fn update(&mut self, dt: f32) -> bool;
+    fn draw(&self, canvas: &mut Canvas);
+    fn update(&mut self, dt: f32, arena_size: Vec2) -> bool;
     fn collider_info(&self) -> ColliderInfo<'_>;
     fn deal_damage(&mut self, other_vel: &Vec2, other_mass: f32);
 }
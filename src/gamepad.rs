@@ -0,0 +1,109 @@
+use crate::ui::Nav;
+
+// What a gamepad can do outside of the game itself: find its way around the
+// menus, and pause.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(not(feature = "gamepad"), allow(dead_code))]
+pub enum PadEvent {
+    Nav(Nav),
+    Start,
+}
+
+#[cfg(feature = "gamepad")]
+mod pads {
+    use gilrs::{Axis, Button, EventType, Gilrs};
+
+    use super::PadEvent;
+    use crate::ui::Nav;
+
+    // A stick has to be pushed this far to count as a press, and let back
+    // under the lower threshold before it can press again.
+    const STICK_PRESS: f32 = 0.6;
+    const STICK_RELEASE: f32 = 0.3;
+
+    pub struct Gamepads {
+        gilrs: Option<Gilrs>,
+        stick: (bool, bool), // whether x and y are held past the threshold
+    }
+
+    impl Gamepads {
+        pub fn connect() -> Self {
+            let gilrs = match Gilrs::new() {
+                Ok(gilrs) => Some(gilrs),
+                Err(err) => {
+                    eprintln!("warning: gamepads disabled: {}", err);
+                    None
+                }
+            };
+            Gamepads {
+                gilrs,
+                stick: (false, false),
+            }
+        }
+
+        pub fn poll(&mut self) -> Vec<PadEvent> {
+            let mut events = Vec::new();
+            let Some(gilrs) = &mut self.gilrs else {
+                return events;
+            };
+            while let Some(event) = gilrs.next_event() {
+                match event.event {
+                    EventType::ButtonPressed(button, _) => {
+                        let event = match button {
+                            Button::DPadUp => PadEvent::Nav(Nav::Up),
+                            Button::DPadDown => PadEvent::Nav(Nav::Down),
+                            Button::DPadLeft => PadEvent::Nav(Nav::Left),
+                            Button::DPadRight => PadEvent::Nav(Nav::Right),
+                            Button::South => PadEvent::Nav(Nav::Activate),
+                            Button::East => PadEvent::Nav(Nav::Back),
+                            Button::Start => PadEvent::Start,
+                            _ => continue,
+                        };
+                        events.push(event);
+                    }
+                    EventType::AxisChanged(Axis::LeftStickX, value, _) => {
+                        events.extend(stick(&mut self.stick.0, value, Nav::Left, Nav::Right));
+                    }
+                    // up is positive
+                    EventType::AxisChanged(Axis::LeftStickY, value, _) => {
+                        events.extend(stick(&mut self.stick.1, value, Nav::Down, Nav::Up));
+                    }
+                    _ => {}
+                }
+            }
+            events
+        }
+    }
+
+    fn stick(held: &mut bool, value: f32, negative: Nav, positive: Nav) -> Option<PadEvent> {
+        if *held {
+            *held = value.abs() > STICK_RELEASE;
+            return None;
+        }
+        if value.abs() < STICK_PRESS {
+            return None;
+        }
+        *held = true;
+        Some(PadEvent::Nav(if value < 0.0 { negative } else { positive }))
+    }
+}
+
+// Built without the `gamepad` feature there are never any gamepads.
+#[cfg(not(feature = "gamepad"))]
+mod pads {
+    use super::PadEvent;
+
+    pub struct Gamepads;
+
+    impl Gamepads {
+        pub fn connect() -> Self {
+            Gamepads
+        }
+
+        pub fn poll(&mut self) -> Vec<PadEvent> {
+            Vec::new()
+        }
+    }
+}
+
+pub use pads::Gamepads;
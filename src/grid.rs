@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use speedy2d::dimen::Vec2;
+
+// A uniform grid over the arena for the broad phase of collision detection:
+// each circle is listed in every cell its bounding box touches, so circles
+// that overlap always share a cell. Cells are only stored once something is
+// in them, which also covers enemies wrapping around past the arena edges.
+#[derive(Clone, Default)]
+pub struct Grid {
+    pub cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl Grid {
+    fn cell_range(&self, pos: Vec2, radius: f32) -> ((i32, i32), (i32, i32)) {
+        let cell = |value: f32| (value / self.cell_size).floor() as i32;
+        (
+            (cell(pos.x - radius), cell(pos.y - radius)),
+            (cell(pos.x + radius), cell(pos.y + radius)),
+        )
+    }
+
+    // Lists the circles by their index in `circles`.
+    pub fn build(&mut self, cell_size: f32, circles: impl Iterator<Item = (Vec2, f32)>) {
+        self.cell_size = cell_size;
+        // emptied rather than dropped, keeping their allocations
+        self.cells.retain(|_, indices| {
+            indices.clear();
+            indices.capacity() > 0
+        });
+        for (index, (pos, radius)) in circles.enumerate() {
+            let ((min_x, min_y), (max_x, max_y)) = self.cell_range(pos, radius);
+            for x in min_x..=max_x {
+                for y in min_y..=max_y {
+                    self.cells.entry((x, y)).or_default().push(index);
+                }
+            }
+        }
+    }
+
+    // The circles that might overlap the given one, in increasing order of
+    // index, so they come up in the same order as when checking them all.
+    pub fn query(&self, pos: Vec2, radius: f32, out: &mut Vec<usize>) {
+        out.clear();
+        let ((min_x, min_y), (max_x, max_y)) = self.cell_range(pos, radius);
+        for x in min_x..=max_x {
+            for y in min_y..=max_y {
+                if let Some(indices) = self.cells.get(&(x, y)) {
+                    out.extend_from_slice(indices);
+                }
+            }
+        }
+        out.sort_unstable();
+        out.dedup();
+    }
+
+    // The cells with anything in them, and how many circles each holds.
+    pub fn occupied(&self) -> impl Iterator<Item = ((i32, i32), usize)> + '_ {
+        self.cells
+            .iter()
+            .filter(|(_, indices)| !indices.is_empty())
+            .map(|(cell, indices)| (*cell, indices.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_finds_every_overlapping_circle_once() {
+        let circles = [
+            (Vec2::new(10.0, 10.0), 5.0),
+            (Vec2::new(60.0, 60.0), 30.0), // spans several cells
+            (Vec2::new(500.0, 500.0), 5.0),
+            (Vec2::new(-40.0, 15.0), 5.0), // off the arena, as wrapping enemies are
+        ];
+        let mut grid = Grid::default();
+        grid.build(32.0, circles.into_iter());
+
+        let mut found = Vec::new();
+        grid.query(Vec2::new(40.0, 40.0), 20.0, &mut found);
+        assert_eq!(found, [0, 1]);
+        grid.query(Vec2::new(-35.0, 15.0), 1.0, &mut found);
+        assert_eq!(found, [3]);
+        grid.query(Vec2::new(300.0, 300.0), 10.0, &mut found);
+        assert!(found.is_empty());
+
+        // brute force agrees on anything that actually overlaps
+        for (pos, radius) in circles {
+            grid.query(pos, radius, &mut found);
+            for (index, (other, other_radius)) in circles.iter().enumerate() {
+                if (pos - *other).magnitude() <= radius + other_radius {
+                    assert!(found.contains(&index));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rebuilding_forgets_old_circles() {
+        let mut grid = Grid::default();
+        grid.build(32.0, [(Vec2::new(10.0, 10.0), 5.0)].into_iter());
+        grid.build(32.0, [(Vec2::new(200.0, 200.0), 5.0)].into_iter());
+        let mut found = Vec::new();
+        grid.query(Vec2::new(10.0, 10.0), 5.0, &mut found);
+        assert!(found.is_empty());
+        assert_eq!(grid.occupied().collect::<Vec<_>>(), [((6, 6), 1)]);
+    }
+}
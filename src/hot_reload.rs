@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const POLL_INTERVAL: f32 = 0.5; // seconds between two scans
+
+// Notices changes to files by polling their modification times, so edits to
+// the assets or the config file can be picked up while the game runs.
+// Directories are watched recursively.
+pub struct FileWatcher {
+    roots: Vec<PathBuf>,
+    stamps: HashMap<PathBuf, SystemTime>,
+    elapsed: f32,
+}
+
+fn scan(path: &Path, stamps: &mut HashMap<PathBuf, SystemTime>) {
+    let Ok(metadata) = fs::metadata(path) else {
+        return;
+    };
+    if metadata.is_dir() {
+        if let Ok(entries) = fs::read_dir(path) {
+            for entry in entries.flatten() {
+                scan(&entry.path(), stamps);
+            }
+        }
+    } else if let Ok(modified) = metadata.modified() {
+        stamps.insert(path.to_path_buf(), modified);
+    }
+}
+
+impl FileWatcher {
+    pub fn new(roots: Vec<PathBuf>) -> Self {
+        let mut watcher = FileWatcher {
+            roots,
+            stamps: HashMap::new(),
+            elapsed: 0.0,
+        };
+        watcher.stamps = watcher.scan();
+        watcher
+    }
+
+    fn scan(&self) -> HashMap<PathBuf, SystemTime> {
+        let mut stamps = HashMap::new();
+        for root in &self.roots {
+            scan(root, &mut stamps);
+        }
+        stamps
+    }
+
+    // Returns the files that were changed, added or removed since the last
+    // scan. Scans at most every POLL_INTERVAL seconds.
+    pub fn poll(&mut self, dt: f32) -> Vec<PathBuf> {
+        self.elapsed += dt;
+        if self.elapsed < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.elapsed = 0.0;
+
+        let stamps = self.scan();
+        let mut changed: Vec<PathBuf> = stamps
+            .iter()
+            .filter(|(path, stamp)| self.stamps.get(*path) != Some(stamp))
+            .map(|(path, _)| path.clone())
+            .collect();
+        changed.extend(self.stamps.keys().filter(|path| !stamps.contains_key(*path)).cloned());
+        self.stamps = stamps;
+        changed
+    }
+}
@@ -0,0 +1,176 @@
+use speedy2d::color::Color;
+use speedy2d::dimen::Vec2;
+use speedy2d::font::{Font, FormattedTextBlock, TextLayout, TextOptions};
+use speedy2d::shape::Rectangle;
+
+use crate::canvas::Canvas;
+use crate::game::Game;
+
+const MARGIN: f32 = 20.0;
+const BAR_WIDTH: f32 = 240.0;
+const BAR_HEIGHT: f32 = 14.0;
+const LABEL_SIZE: f32 = 16.0;
+const VALUE_SIZE: f32 = 28.0;
+
+const GAUGE_SEGMENTS: usize = 10;
+const SEGMENT_GAP: f32 = 4.0;
+
+// seconds the "WAVE N" banner is shown, and spends fading in and out
+const BANNER_TIME: f32 = 2.0;
+const BANNER_FADE_IN: f32 = 0.25;
+const BANNER_FADE_OUT: f32 = 0.75;
+const BANNER_SIZE: f32 = 72.0;
+const BANNER_SLIDE: f32 = 30.0; // how far the banner drops in from
+
+const TRACK_COLOR: Color = Color::from_rgba(1.0, 1.0, 1.0, 0.15);
+const LABEL_COLOR: Color = Color::from_rgba(1.0, 1.0, 1.0, 0.6);
+
+// A text layout that is only redone when the value it shows, or the size it
+// is drawn at, changes.
+#[derive(Default)]
+struct CachedText<K> {
+    cached: Option<(K, f32, FormattedTextBlock)>,
+}
+
+impl<K: PartialEq + Copy> CachedText<K> {
+    fn layout(&mut self, font: &Font, key: K, size: f32, text: impl FnOnce(K) -> String) -> &FormattedTextBlock {
+        if !self.cached.as_ref().is_some_and(|(old, old_size, _)| *old == key && *old_size == size) {
+            self.cached = None;
+        }
+        let (_, _, layout) = self
+            .cached
+            .get_or_insert_with(|| (key, size, font.layout_text(&text(key), size, TextOptions::new())));
+        layout
+    }
+}
+
+// The wave in play. `Game::level` has already moved on to the next one.
+fn wave(game: &Game) -> u32 {
+    game.level.saturating_sub(1)
+}
+
+fn with_alpha(color: Color, alpha: f32) -> Color {
+    Color::from_rgba(color.r(), color.g(), color.b(), color.a() * alpha)
+}
+
+// The health bar and super bang gauge in the top left, score and accuracy in
+// the top right, and a banner when a new wave starts.
+#[derive(Default)]
+pub struct Hud {
+    health_label: CachedText<()>,
+    gauge_label: CachedText<()>,
+    wave: CachedText<u32>,
+    super_bangs: CachedText<u32>,
+    score: CachedText<u64>,
+    accuracy: CachedText<Option<u32>>,
+    banner: CachedText<u32>,
+    banner_wave: u32,
+    banner_time: f32, // seconds since the banner appeared
+    time: f32,
+}
+
+impl Hud {
+    // Called while the game runs, so the banner waits out a pause.
+    pub fn update(&mut self, dt: f32, game: &Game) {
+        self.time += dt;
+        self.banner_time += dt;
+        if wave(game) != self.banner_wave {
+            self.banner_wave = wave(game);
+            self.banner_time = 0.0;
+        }
+    }
+
+    pub fn draw(&mut self, canvas: &mut Canvas, font: &Font, game: &Game, hud_size: Vec2) {
+        let mut y = MARGIN;
+
+        let label = self.health_label.layout(font, (), canvas.font_size(LABEL_SIZE), |_| "HEALTH".into());
+        canvas.draw_text(Vec2::new(MARGIN, y), LABEL_COLOR, label);
+        y += LABEL_SIZE + 4.0;
+        // the player's size is their health
+        let health = (game.player.radius / game.config.player_radius).clamp(0.0, 1.0);
+        let color = Color::from_rgb(1.0 - health, health, 0.2);
+        self.draw_bar(canvas, Vec2::new(MARGIN, y), BAR_WIDTH, health, color);
+        y += BAR_HEIGHT + 12.0;
+
+        let label = self.gauge_label.layout(font, (), canvas.font_size(LABEL_SIZE), |_| "SUPER BANG".into());
+        canvas.draw_text(Vec2::new(MARGIN, y), LABEL_COLOR, label);
+        y += LABEL_SIZE + 4.0;
+        self.draw_gauge(canvas, Vec2::new(MARGIN, y), game);
+        let count = self.super_bangs.layout(font, game.super_bang, canvas.font_size(LABEL_SIZE), |count| {
+            format!("x{}", count)
+        });
+        canvas.draw_text(Vec2::new(MARGIN + BAR_WIDTH + 10.0, y - 2.0), Color::WHITE, count);
+        y += BAR_HEIGHT + 12.0;
+
+        let text = self.wave.layout(font, wave(game), canvas.font_size(VALUE_SIZE), |wave| {
+            format!("WAVE {}", wave)
+        });
+        canvas.draw_text(Vec2::new(MARGIN, y), Color::WHITE, text);
+
+        let right = hud_size.x - MARGIN;
+        let text = self.score.layout(font, game.score, canvas.font_size(VALUE_SIZE), |score| {
+            format!("SCORE {}", score)
+        });
+        let width = text.width() / canvas.view.scale;
+        canvas.draw_text(Vec2::new(right - width, MARGIN), Color::WHITE, text);
+
+        let accuracy = (game.bullets_fired > 0)
+            .then(|| (game.bullets_hit as f32 / game.bullets_fired as f32 * 100.0).round() as u32);
+        let text = self.accuracy.layout(font, accuracy, canvas.font_size(LABEL_SIZE), |accuracy| match accuracy {
+            Some(percent) => format!("ACCURACY {}%", percent),
+            None => "ACCURACY -".into(),
+        });
+        let width = text.width() / canvas.view.scale;
+        canvas.draw_text(Vec2::new(right - width, MARGIN + VALUE_SIZE + 6.0), LABEL_COLOR, text);
+
+        self.draw_banner(canvas, font, hud_size);
+    }
+
+    fn draw_bar(&self, canvas: &mut Canvas, pos: Vec2, width: f32, fill: f32, color: Color) {
+        let size = Vec2::new(width, BAR_HEIGHT);
+        canvas.draw_rectangle(&Rectangle::new(pos, pos + size), TRACK_COLOR);
+        if fill > 0.0 {
+            canvas.draw_rectangle(&Rectangle::new(pos, pos + Vec2::new(width * fill, BAR_HEIGHT)), color);
+        }
+    }
+
+    // How much of the stock of super bangs has been loaded into the next one.
+    // It only fills while charging; the last segment fills gradually.
+    fn draw_gauge(&self, canvas: &mut Canvas, pos: Vec2, game: &Game) {
+        let loaded = game.charged_super_bang as f32;
+        let available = loaded + (game.super_bang * game.config.super_bang_charge_rate) as f32;
+        let fill = if available > 0.0 { loaded / available } else { 0.0 };
+        let color = if game.charging {
+            // pulses while the button is held
+            with_alpha(Color::YELLOW, 0.75 + 0.25 * (self.time * 10.0).sin())
+        } else {
+            Color::from_rgb(1.0, 0.6, 0.1)
+        };
+
+        let segment = (BAR_WIDTH - SEGMENT_GAP * (GAUGE_SEGMENTS - 1) as f32) / GAUGE_SEGMENTS as f32;
+        for index in 0..GAUGE_SEGMENTS {
+            let left = pos + Vec2::new(index as f32 * (segment + SEGMENT_GAP), 0.0);
+            let segment_fill = (fill * GAUGE_SEGMENTS as f32 - index as f32).clamp(0.0, 1.0);
+            self.draw_bar(canvas, left, segment, segment_fill, color);
+        }
+    }
+
+    // Drops in from above and fades out in the middle of the screen.
+    fn draw_banner(&mut self, canvas: &mut Canvas, font: &Font, hud_size: Vec2) {
+        if self.banner_wave == 0 || self.banner_time >= BANNER_TIME {
+            return;
+        }
+        let appear = (self.banner_time / BANNER_FADE_IN).min(1.0);
+        let disappear = ((BANNER_TIME - self.banner_time) / BANNER_FADE_OUT).min(1.0);
+        let alpha = appear.min(disappear);
+        // ease out, so it settles into place
+        let offset = BANNER_SLIDE * (1.0 - appear).powi(2);
+
+        let text = self.banner.layout(font, self.banner_wave, canvas.font_size(BANNER_SIZE), |wave| {
+            format!("WAVE {}", wave)
+        });
+        let width = text.width() / canvas.view.scale;
+        let pos = Vec2::new((hud_size.x - width) / 2.0, hud_size.y * 0.3 - offset);
+        canvas.draw_text(pos, with_alpha(Color::WHITE, alpha), text);
+    }
+}
@@ -0,0 +1,145 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+// A single `key = value` line from an INI-style file. Keys that appear before
+// the first `[section]` header have an empty section.
+pub struct Entry {
+    pub section: String,
+    pub key: String,
+    pub value: String,
+    pub line: usize,
+}
+
+pub fn parse(text: &str) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut section = String::new();
+
+    for (index, raw) in text.lines().enumerate() {
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        if let Some(rest) = line.strip_prefix('[') {
+            match rest.strip_suffix(']') {
+                Some(name) => section = name.trim().to_string(),
+                None => bail!("line {}: unterminated section header `{}`", index + 1, line),
+            }
+            continue;
+        }
+
+        match line.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => entries.push(Entry {
+                section: section.clone(),
+                key: key.trim().to_string(),
+                value: value.trim().to_string(),
+                line: index + 1,
+            }),
+            _ => bail!("line {}: expected `key = value`, found `{}`", index + 1, line),
+        }
+    }
+
+    Ok(entries)
+}
+
+pub fn load(path: &Path) -> Result<Vec<Entry>> {
+    let text = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    parse(&text).with_context(|| format!("parsing {}", path.display()))
+}
+
+// Sets `key = value` pairs in one section of a file, leaving every other line
+// (comments included) untouched. Keys that aren't there yet are added at the
+// end of the section, and the section or the file are created if needed.
+pub fn update(path: &Path, section: &str, values: &[(&str, String)]) -> Result<()> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err).with_context(|| format!("reading {}", path.display())),
+    };
+
+    let mut lines: Vec<String> = text.lines().map(String::from).collect();
+    let mut missing: Vec<&(&str, String)> = values.iter().collect();
+    let mut current = String::new();
+    // line after which new keys for the section are inserted
+    let mut insert_at = if section.is_empty() { Some(0) } else { None };
+
+    for (index, line) in lines.iter_mut().enumerate() {
+        let trimmed = line.trim();
+        if let Some(name) = trimmed.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            current = name.trim().to_string();
+            if current == section {
+                insert_at = Some(index + 1);
+            }
+            continue;
+        }
+        if current != section || trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with(';') {
+            continue;
+        }
+
+        insert_at = Some(index + 1);
+        let key = trimmed.split_once('=').map(|(key, _)| key.trim().to_string());
+        if let Some(key) = key {
+            if let Some(position) = missing.iter().position(|(name, _)| *name == key) {
+                *line = format!("{} = {}", key, missing[position].1);
+                missing.remove(position);
+            }
+        }
+    }
+
+    let new_lines: Vec<String> = missing.iter().map(|(key, value)| format!("{} = {}", key, value)).collect();
+    match insert_at {
+        Some(index) => {
+            lines.splice(index..index, new_lines);
+        }
+        None => {
+            if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push(format!("[{}]", section));
+            lines.extend(new_lines);
+        }
+    }
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    fs::write(path, text).with_context(|| format!("writing {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sections_and_skips_comments() {
+        let entries = parse("top = 1\n# comment\n; also a comment\n\n[ game ]\n  speed =  2.5 \nname = a = b\n").unwrap();
+        let found: Vec<(&str, &str, &str, usize)> = entries
+            .iter()
+            .map(|entry| (entry.section.as_str(), entry.key.as_str(), entry.value.as_str(), entry.line))
+            .collect();
+        assert_eq!(found, [("", "top", "1", 1), ("game", "speed", "2.5", 6), ("game", "name", "a = b", 7)]);
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert!(parse("[game\nspeed = 1").err().unwrap().to_string().starts_with("line 1:"));
+        assert!(parse("speed = 1\njust words").err().unwrap().to_string().starts_with("line 2:"));
+        assert!(parse(" = 1").is_err());
+    }
+
+    #[test]
+    fn update_keeps_other_lines() {
+        let path = std::env::temp_dir().join(format!("colorbang-ini-test-{}.ini", std::process::id()));
+        fs::write(&path, "# settings\nwidth = 800\n\n[audio]\n# loud\nmaster = 1.0\n\n[keys]\nfire = space\n").unwrap();
+        update(&path, "audio", &[("master", "0.5".to_string()), ("music", "0.2".to_string())]).unwrap();
+        update(&path, "game", &[("player_speed", "300".to_string())]).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            text,
+            "# settings\nwidth = 800\n\n[audio]\n# loud\nmaster = 0.5\nmusic = 0.2\n\n[keys]\nfire = space\n\n[game]\nplayer_speed = 300\n"
+        );
+    }
+}
@@ -0,0 +1,275 @@
+use speedy2d::dimen::Vec2;
+use speedy2d::window::{MouseButton, VirtualKeyCode};
+
+// Keys that can be bound to a control, by their name in the config file.
+// Escape and the function keys are left out as the game uses them itself.
+const KEY_NAMES: &[(&str, VirtualKeyCode)] = &[
+    ("a", VirtualKeyCode::A),
+    ("b", VirtualKeyCode::B),
+    ("c", VirtualKeyCode::C),
+    ("d", VirtualKeyCode::D),
+    ("e", VirtualKeyCode::E),
+    ("f", VirtualKeyCode::F),
+    ("g", VirtualKeyCode::G),
+    ("h", VirtualKeyCode::H),
+    ("i", VirtualKeyCode::I),
+    ("j", VirtualKeyCode::J),
+    ("k", VirtualKeyCode::K),
+    ("l", VirtualKeyCode::L),
+    ("m", VirtualKeyCode::M),
+    ("n", VirtualKeyCode::N),
+    ("o", VirtualKeyCode::O),
+    ("p", VirtualKeyCode::P),
+    ("q", VirtualKeyCode::Q),
+    ("r", VirtualKeyCode::R),
+    ("s", VirtualKeyCode::S),
+    ("t", VirtualKeyCode::T),
+    ("u", VirtualKeyCode::U),
+    ("v", VirtualKeyCode::V),
+    ("w", VirtualKeyCode::W),
+    ("x", VirtualKeyCode::X),
+    ("y", VirtualKeyCode::Y),
+    ("z", VirtualKeyCode::Z),
+    ("0", VirtualKeyCode::Key0),
+    ("1", VirtualKeyCode::Key1),
+    ("2", VirtualKeyCode::Key2),
+    ("3", VirtualKeyCode::Key3),
+    ("4", VirtualKeyCode::Key4),
+    ("5", VirtualKeyCode::Key5),
+    ("6", VirtualKeyCode::Key6),
+    ("7", VirtualKeyCode::Key7),
+    ("8", VirtualKeyCode::Key8),
+    ("9", VirtualKeyCode::Key9),
+    ("space", VirtualKeyCode::Space),
+    ("backspace", VirtualKeyCode::Backspace),
+    ("enter", VirtualKeyCode::Return),
+    ("tab", VirtualKeyCode::Tab),
+    ("up", VirtualKeyCode::Up),
+    ("down", VirtualKeyCode::Down),
+    ("left", VirtualKeyCode::Left),
+    ("right", VirtualKeyCode::Right),
+    ("left_shift", VirtualKeyCode::LShift),
+    ("right_shift", VirtualKeyCode::RShift),
+    ("left_ctrl", VirtualKeyCode::LControl),
+    ("right_ctrl", VirtualKeyCode::RControl),
+    ("left_alt", VirtualKeyCode::LAlt),
+    ("right_alt", VirtualKeyCode::RAlt),
+];
+
+pub fn key_name(key: VirtualKeyCode) -> Option<&'static str> {
+    KEY_NAMES.iter().find(|(_, known)| *known == key).map(|(name, _)| *name)
+}
+
+pub fn parse_key(name: &str) -> Option<VirtualKeyCode> {
+    let name = name.to_ascii_lowercase();
+    KEY_NAMES.iter().find(|(known, _)| *known == name).map(|(_, key)| *key)
+}
+
+// What the keyboard can be bound to.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Control {
+    MoveForward,
+    MoveBackward,
+    RotateLeft,
+    RotateRight,
+    Fire,
+    Charge,
+    Pause,
+}
+
+impl Control {
+    pub const ALL: [Control; 7] = [
+        Control::MoveForward,
+        Control::MoveBackward,
+        Control::RotateLeft,
+        Control::RotateRight,
+        Control::Fire,
+        Control::Charge,
+        Control::Pause,
+    ];
+
+    // The key in the `[keys]` section of the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Control::MoveForward => "forward",
+            Control::MoveBackward => "backward",
+            Control::RotateLeft => "rotate_left",
+            Control::RotateRight => "rotate_right",
+            Control::Fire => "fire",
+            Control::Charge => "charge",
+            Control::Pause => "pause",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Control> {
+        Control::ALL.into_iter().find(|control| control.name() == name)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Control::MoveForward => "Move forward",
+            Control::MoveBackward => "Move backward",
+            Control::RotateLeft => "Rotate left",
+            Control::RotateRight => "Rotate right",
+            Control::Fire => "Fire",
+            Control::Charge => "Charge super bang",
+            Control::Pause => "Pause",
+        }
+    }
+}
+
+// The key for each control. Escape always toggles the cursor.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct KeyBindings {
+    keys: [VirtualKeyCode; Control::ALL.len()],
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            keys: [
+                VirtualKeyCode::W,
+                VirtualKeyCode::S,
+                VirtualKeyCode::A,
+                VirtualKeyCode::D,
+                VirtualKeyCode::Space,
+                VirtualKeyCode::R,
+                VirtualKeyCode::Backspace,
+            ],
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn key(&self, control: Control) -> VirtualKeyCode {
+        self.keys[control as usize]
+    }
+
+    // A key can only do one thing, so a control that had it gets the key the
+    // other one is giving up.
+    pub fn set(&mut self, control: Control, key: VirtualKeyCode) {
+        let old = self.key(control);
+        if let Some(other) = self.keys.iter_mut().find(|bound| **bound == key) {
+            *other = old;
+        }
+        self.keys[control as usize] = key;
+    }
+
+    fn control(&self, key: VirtualKeyCode) -> Option<Control> {
+        Control::ALL.into_iter().find(|control| self.key(*control) == key)
+    }
+
+    pub fn action_down(&self, key: VirtualKeyCode) -> Option<Action> {
+        match self.control(key)? {
+            Control::Pause => Some(Action::TogglePause),
+            Control::Fire => Some(Action::FireStart),
+            Control::Charge => Some(Action::ChargeStart),
+            Control::RotateLeft => Some(Action::RotateLeft),
+            Control::RotateRight => Some(Action::RotateRight),
+            Control::MoveForward => Some(Action::MoveForward),
+            Control::MoveBackward => Some(Action::MoveBackward),
+        }
+    }
+
+    pub fn action_up(&self, key: VirtualKeyCode) -> Option<Action> {
+        if key == VirtualKeyCode::Escape {
+            return Some(Action::ToggleCursor);
+        }
+        match self.control(key)? {
+            Control::Fire => Some(Action::FireStop),
+            Control::Charge => Some(Action::ChargeRelease),
+            Control::RotateLeft | Control::RotateRight => Some(Action::RotateStop),
+            _ => None,
+        }
+    }
+
+    // Every binding, as written to the `[keys]` section.
+    pub fn values(&self) -> Vec<(&'static str, String)> {
+        Control::ALL
+            .iter()
+            .map(|control| (control.name(), key_name(self.key(*control)).unwrap_or("?").to_string()))
+            .collect()
+    }
+}
+
+// Everything the player can ask the game to do. Window events are translated
+// into actions before they reach `Game`, which keeps the simulation free of
+// windowing types and lets replays store input in a readable form.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Action {
+    Aim(Vec2),
+    FireStart,
+    FireStop,
+    ChargeStart,
+    ChargeRelease,
+    RotateLeft,
+    RotateRight,
+    RotateStop,
+    MoveForward,
+    MoveBackward,
+    TogglePause,
+    ToggleCursor,
+    SpawnDebugWave,
+}
+
+impl Action {
+    pub fn from_mouse_down(button: MouseButton) -> Option<Action> {
+        match button {
+            MouseButton::Left => Some(Action::FireStart),
+            MouseButton::Right => Some(Action::ChargeStart),
+            MouseButton::Middle => Some(Action::SpawnDebugWave),
+            _ => None,
+        }
+    }
+
+    pub fn from_mouse_up(button: MouseButton) -> Option<Action> {
+        match button {
+            MouseButton::Left => Some(Action::FireStop),
+            MouseButton::Right => Some(Action::ChargeRelease),
+            _ => None,
+        }
+    }
+
+    pub fn to_text(self) -> String {
+        match self {
+            Action::Aim(pos) => format!("aim {} {}", pos.x, pos.y),
+            Action::FireStart => "fire_start".to_string(),
+            Action::FireStop => "fire_stop".to_string(),
+            Action::ChargeStart => "charge_start".to_string(),
+            Action::ChargeRelease => "charge_release".to_string(),
+            Action::RotateLeft => "rotate_left".to_string(),
+            Action::RotateRight => "rotate_right".to_string(),
+            Action::RotateStop => "rotate_stop".to_string(),
+            Action::MoveForward => "move_forward".to_string(),
+            Action::MoveBackward => "move_backward".to_string(),
+            Action::TogglePause => "toggle_pause".to_string(),
+            Action::ToggleCursor => "toggle_cursor".to_string(),
+            Action::SpawnDebugWave => "spawn_debug_wave".to_string(),
+        }
+    }
+
+    pub fn from_text(text: &str) -> Option<Action> {
+        let mut parts = text.split_whitespace();
+        let action = match parts.next()? {
+            "aim" => {
+                let x = parts.next()?.parse().ok()?;
+                let y = parts.next()?.parse().ok()?;
+                Action::Aim(Vec2::new(x, y))
+            }
+            "fire_start" => Action::FireStart,
+            "fire_stop" => Action::FireStop,
+            "charge_start" => Action::ChargeStart,
+            "charge_release" => Action::ChargeRelease,
+            "rotate_left" => Action::RotateLeft,
+            "rotate_right" => Action::RotateRight,
+            "rotate_stop" => Action::RotateStop,
+            "move_forward" => Action::MoveForward,
+            "move_backward" => Action::MoveBackward,
+            "toggle_pause" => Action::TogglePause,
+            "toggle_cursor" => Action::ToggleCursor,
+            "spawn_debug_wave" => Action::SpawnDebugWave,
+            _ => return None,
+        };
+        Some(action)
+    }
+}
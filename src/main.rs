@@ -1,370 +1,729 @@
+mod assets;
+mod batch;
 mod bullet;
+mod camera;
+mod canvas;
+mod cli;
+mod clip;
+mod config;
+mod console;
+mod debug;
 mod enemy;
+mod game;
+mod gamepad;
 mod game_entity;
+mod grid;
+mod hot_reload;
+mod hud;
+mod ini;
+mod input;
+mod menu;
+mod music;
+mod palette;
 mod particle;
 mod player;
+mod radar;
+mod raster;
+mod render;
+mod replay;
+mod rng;
+mod scores;
+mod settings;
 mod sound;
+mod sound_bank;
+mod spectate;
+mod synth;
+mod trail;
+mod ui;
 
-use crate::bullet::Bullet;
 use speedy2d::color::Color;
-use speedy2d::dimen::{Vec2, Vector2};
+use speedy2d::dimen::{UVec2, Vec2};
 use speedy2d::font::TextLayout;
 use speedy2d::font::{Font, TextOptions};
+use speedy2d::image::ImageDataType;
 use speedy2d::shape::Rectangle;
 use speedy2d::time::Stopwatch;
 use speedy2d::window::{
-    KeyScancode, MouseButton, VirtualKeyCode, WindowHandler, WindowHelper, WindowStartupInfo,
+    KeyScancode, MouseButton, MouseScrollDistance, VirtualKeyCode, WindowCreationOptions, WindowHandler, WindowHelper,
+    WindowPosition, WindowSize, WindowStartupInfo,
 };
 use speedy2d::{Graphics2D, Window};
 
-use crate::enemy::Enemy;
-use crate::game_entity::{collide, GameEntity};
-use crate::particle::Particle;
-use crate::player::Player;
-use crate::sound::SoundType;
-
-const WIDTH: f32 = 1280.0;
-const HEIGHT: f32 = 960.0;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::assets::Assets;
+use crate::batch::ShapeBatch;
+use crate::camera::Camera;
+use crate::canvas::Canvas;
+use crate::clip::{ClipFormat, ClipRecorder};
+use crate::cli::{Command, Options};
+use crate::console::{Console, ConsoleCommand};
+use crate::config::GameConfig;
+use crate::game::Game;
+use crate::gamepad::{Gamepads, PadEvent};
+use crate::hot_reload::FileWatcher;
+use crate::hud::Hud;
+use crate::input::Action;
+use crate::menu::{Menu, MenuAction, Screen};
+use crate::render::Scene;
+use crate::replay::{Recorder, Replay, ReplayHeader};
+use crate::settings::Settings;
+use crate::sound::SoundPlayer;
+use crate::spectate::{SpectateServer, SpectatorWindowHandler};
+use crate::trail::EffectsStyle;
+
+// default arena size, in world units
+const ARENA_WIDTH: f32 = 1280.0;
+const ARENA_HEIGHT: f32 = 960.0;
 
 const COL_PLAYER: u8 = 0b00000001;
 const COL_ENEMY: u8 = 0b00000010;
 const COL_BULLET: u8 = 0b00000100;
 
-const COOLDOWN_RATE: f32 = 0.2; // cooldown for firing bullets
+const HEADLESS_DT: f32 = 1.0 / 60.0; // fixed step used by --headless --ticks
+const FRAME_TIME_HISTORY: usize = 120; // frames averaged by the frame time overlay
+const STATUS_TIME: f32 = 3.0; // seconds a reload message stays on screen
 
 struct MyWindowHandler {
     timer: Stopwatch,
     frame_time: f64,
-    mouse_pos: Vec2,
-
-    paused: bool,
-
-    cursor_visible: bool,
+    frame_times: VecDeque<f32>,
+    show_frame_time: bool,
+    show_debug: bool,
+    batch: ShapeBatch,
 
     font: Font,
-    sound: sound::SoundPlayer,
-
-    level: u32,
-    super_bang: u32,
-    charged_super_bang: u32,
-    charging: bool,
-
-    bullets_fired: u32,
-    bullets_hit: u32,
-
-    firing: bool,
-    firing_cooldown: f32,
-
-    player: Player,
-    enemies: Vec<Enemy>,
-    bullets: Vec<Bullet>,
-    particles: Vec<Particle>,
+    assets: Assets,
+    config_path: PathBuf,
+    watcher: FileWatcher,
+    asset_error: Option<String>,
+    config_error: Option<String>,
+    // what the last reload did, and for how much longer it is shown
+    reload_status: Option<(String, f32)>,
+    // the [game] settings as the config file last had them, so a reload only
+    // changes the ones edited there and keeps tuning from the console
+    file_config: GameConfig,
+    spectate: Option<SpectateServer>,
+    recorder: Option<Recorder>,
+    replay: Option<Replay>,
+    replay_finished: bool,
+
+    window_size: UVec2,
+    needs_clear: bool,
+    mouse_screen_pos: Option<Vec2>,
+    camera: Camera,
+    menu: Menu,
+    hud: Hud,
+    console: Console,
+    gamepads: Gamepads,
+    cursor_shown: bool,
+    // a key pressed in the menu, whose release is kept from the game
+    swallow_key_up: Option<VirtualKeyCode>,
+
+    game: Game,
+    // for starting over; without a fixed seed every game gets a new one
+    seed: Option<u64>,
+    start_level: u32,
+    // set from the console; replays keep their own timing
+    time_scale: f32,
+    // game settings were changed from the console, which outlasts restarts
+    tuned: bool,
+    effects: EffectsStyle,
     background_color: Color,
-    background_rect: Rectangle,
+    screenshot_requested: bool,
+    clips: ClipRecorder,
 }
 
 impl MyWindowHandler {
-    pub fn new() -> Self {
-        let enemies = Vec::new();
-        let bullets = Vec::new();
-
-        let particles = Vec::new();
+    pub fn new(
+        assets: Assets,
+        game: Game,
+        camera: Camera,
+        menu: Menu,
+        spectate: Option<SpectateServer>,
+        recorder: Option<Recorder>,
+        replay: Option<Replay>,
+    ) -> Result<Self> {
         let timer = Stopwatch::new().unwrap();
+        let font = assets.load_font()?;
+        let config_path = menu.config_path().to_path_buf();
+        let watch_roots = assets.dir().map(Path::to_path_buf).into_iter().chain([config_path.clone()]).collect();
 
-        let font = Font::new(include_bytes!("../assets/Nasa21-l23X.ttf")).unwrap();
-
-        MyWindowHandler {
-            mouse_pos: Vec2::ZERO,
+        Ok(MyWindowHandler {
             frame_time: timer.secs_elapsed(),
-            sound: sound::SoundPlayer::new().unwrap(),
             timer,
+            frame_times: VecDeque::with_capacity(FRAME_TIME_HISTORY),
+            show_frame_time: false,
+            show_debug: false,
+            batch: ShapeBatch::default(),
             font,
+            assets,
+            config_path,
+            watcher: FileWatcher::new(watch_roots),
+            asset_error: None,
+            config_error: None,
+            reload_status: None,
+            file_config: game.config,
+            spectate,
+            recorder,
+            replay,
+            replay_finished: false,
+
+            window_size: UVec2::new(ARENA_WIDTH as u32, ARENA_HEIGHT as u32),
+            needs_clear: true,
+            mouse_screen_pos: None,
+            camera,
+            menu,
+            hud: Hud::default(),
+            console: Console::default(),
+            gamepads: Gamepads::connect(),
+            cursor_shown: true,
+            swallow_key_up: None,
+
+            game,
+            seed: None,
+            start_level: 1,
+            time_scale: 1.0,
+            tuned: false,
+            effects: EffectsStyle::Trails,
+            background_color: Color::from_int_rgba(0, 0, 0, 50),
+            screenshot_requested: false,
+            clips: ClipRecorder::new(clip::DEFAULT_LENGTH, ClipFormat::Gif),
+        })
+    }
+
+    fn frame_time(&mut self) -> f32 {
+        let old = self.frame_time;
+        self.frame_time = self.timer.secs_elapsed();
+        let dt = (self.frame_time - old) as f32;
+        if self.frame_times.len() == FRAME_TIME_HISTORY {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(dt);
+        dt
+    }
+
+    // Average and worst frame time of the last couple of seconds, and how the
+    // circles and lines were drawn in the previous frame.
+    fn draw_frame_time(&self, canvas: &mut Canvas, hud_size: Vec2) {
+        let count = self.frame_times.len().max(1) as f32;
+        let average = self.frame_times.iter().sum::<f32>() / count;
+        let worst = self.frame_times.iter().copied().fold(0.0, f32::max);
+        let mode = if self.batch.enabled { "batched" } else { "immediate" };
+        let lines = [
+            format!(
+                "{:.1} ms avg, {:.1} ms max ({:.0} fps)",
+                average * 1000.0,
+                worst * 1000.0,
+                1.0 / average.max(0.0001)
+            ),
+            format!("{}: {} shapes, {} flushes (F4 to switch)", mode, self.batch.last_shapes, self.batch.last_flushes),
+        ];
+        for (index, line) in lines.iter().enumerate() {
+            let text = self.font.layout_text(line, canvas.font_size(18.0), TextOptions::new());
+            let pos = Vec2::new(hud_size.x - text.width() / canvas.view.scale - 20.0, 20.0 + index as f32 * 22.0);
+            canvas.draw_text(pos, Color::GREEN, &text);
+        }
+    }
 
-            paused: false,
+    fn display_text(&self, canvas: &mut Canvas, text: &str, pos: Vec2) {
+        let formatted_text_block = self.font.layout_text(text, canvas.font_size(32.0), TextOptions::new());
+        canvas.draw_text(pos, Color::WHITE, &formatted_text_block);
+    }
 
-            cursor_visible: true,
+    // With the smear style the background is translucent, so earlier frames
+    // fade out behind moving things instead of being cleared.
+    fn draw_background(&self, canvas: &mut Canvas) {
+        let (top_left, bottom_right) = self.camera.visible_rect();
+        let color = match self.effects {
+            EffectsStyle::Smear => self.background_color,
+            EffectsStyle::Trails => Color::BLACK,
+        };
+        canvas.draw_rectangle(&Rectangle::new(top_left, bottom_right), color);
+    }
 
-            level: 1,
-            super_bang: 0,
-            charged_super_bang: 0,
-            charging: false,
+    // The aim point is kept in world coordinates, so it has to be refreshed
+    // whenever the camera moves under a stationary mouse.
+    fn update_aim(&mut self) {
+        let Some(screen_pos) = self.mouse_screen_pos else {
+            return;
+        };
+        let world_pos = self.camera.world_view(self.window_size, false).to_world(screen_pos);
+        if world_pos != self.game.mouse_pos {
+            self.handle_action(Some(Action::Aim(world_pos)));
+        }
+    }
 
-            bullets_fired: 0,
-            bullets_hit: 0,
+    // Live input is ignored while a replay is playing so it can't desync.
+    fn handle_action(&mut self, action: Option<Action>) {
+        let Some(action) = action else {
+            return;
+        };
+        if self.replay.is_some() {
+            return;
+        }
 
-            firing: false,
-            firing_cooldown: 0.0,
+        if let Some(recorder) = &mut self.recorder {
+            recorder.action(action);
+        }
+        self.game.handle_action(action);
+    }
 
-            player: Player::new(Vec2::new(WIDTH / 2.0, HEIGHT / 2.0), 20.0),
-            enemies,
-            bullets,
-            particles,
-            background_color: Color::from_int_rgba(0, 0, 0, 50),
-            background_rect: Rectangle::new(Vector2::new(0.0, 0.0), Vector2::new(WIDTH, HEIGHT)),
+    // The cursor is hidden and grabbed for steering with the keyboard, but
+    // always shown in the menus. Replays leave it alone.
+    fn update_cursor(&mut self, helper: &mut WindowHelper<()>) {
+        let show = self.menu.is_open() || self.game.cursor_visible || self.replay.is_some();
+        if show != self.cursor_shown {
+            self.cursor_shown = show;
+            helper.set_cursor_visible(show);
+            let _ = helper.set_cursor_grab(!show);
         }
     }
 
-    fn frame_time(&mut self) -> f32 {
-        let old = self.frame_time;
-        self.frame_time = self.timer.secs_elapsed();
-        (self.frame_time - old) as f32
-    }
-
-    fn display_text(&self, graphics: &mut Graphics2D, text: &str, pos: Vec2) {
-        let formatted_text_block = self.font.layout_text(text, 32.0, TextOptions::new());
-        graphics.draw_text((pos.x, pos.y), Color::WHITE, &formatted_text_block);
-    }
-
-    fn update(&mut self, dt: f32) {
-        self.player.update(dt);
-
-        self.enemies
-            .retain_mut(|enemy: &mut Enemy| enemy.update(dt));
-
-        // update bullets
-        self.bullets.retain_mut(|bullet: &mut Bullet| {
-            bullet.update(dt)
-                && bullet.pos.x > 0.0
-                && bullet.pos.x < WIDTH
-                && bullet.pos.y > 0.0
-                && bullet.pos.y < HEIGHT
-        });
-
-        // render & update particles
-        self.particles.retain_mut(|particle| particle.update(dt));
-
-        // Enemy-player and enemy-bullet collisions
-        for enemy in &mut self.enemies {
-            // Enemy-player collision
-            if collide(enemy, &self.player) {
-                self.sound.play(SoundType::Explode);
-                enemy.deal_damage(&self.player.vel, self.player.radius);
-                self.player.deal_damage(&enemy.vel, enemy.radius);
-                Particle::spawn_particles(
-                    &mut self.particles,
-                    10,
-                    500.0,
-                    self.player.color,
-                    enemy.pos,
-                );
+    fn apply_settings(&mut self) {
+        let settings = self.menu.settings;
+        self.game.sound.set_settings(settings.audio);
+        self.camera.follow = settings.camera_follow;
+        self.camera.shake_enabled = settings.screen_shake;
+        self.game.set_enemy_style(settings.enemy_style);
+        self.batch.enabled = settings.batching;
+        self.effects = settings.effects;
+    }
+
+    fn handle_menu_action(&mut self, helper: &mut WindowHelper<()>, action: MenuAction) {
+        match action {
+            MenuAction::Resume => {
+                self.menu.close();
+                if self.game.paused {
+                    self.handle_action(Some(Action::TogglePause));
+                }
+            }
+            MenuAction::Restart => {
+                self.menu.close();
+                self.restart(self.seed.unwrap_or_else(rand::random));
             }
+            MenuAction::Quit => helper.terminate_loop(),
+            MenuAction::ApplySettings => self.apply_settings(),
+        }
+    }
 
-            // Enemy-bullet collisions
-            self.bullets.retain_mut(|bullet| {
-                if collide(enemy, bullet) {
-                    self.sound.play(SoundType::Explode);
-                    enemy.deal_damage(&bullet.vel, bullet.radius / 20.0);
-                    bullet.deal_damage(&enemy.vel, enemy.radius);
-                    Particle::spawn_particles(
-                        &mut self.particles,
-                        80,
-                        500.0,
-                        enemy.color,
-                        enemy.pos,
-                    );
-                    self.bullets_hit += 1;
-                    false
-                } else {
-                    true
+    // A new run, which only counts as cheated if the time scale or tuning
+    // from the console carry over.
+    fn restart(&mut self, seed: u64) {
+        self.game.restart(seed, self.start_level);
+        self.game.cheated = self.tuned || self.time_scale != 1.0;
+        self.hud = Hud::default();
+    }
+
+    fn run_command(&mut self, command: ConsoleCommand) -> Result<()> {
+        if command.is_cheat() && (self.recorder.is_some() || self.replay.is_some()) {
+            bail!("not while recording or replaying");
+        }
+        // picking a seed starts a new run instead
+        let cheat = command.is_cheat() && !matches!(command, ConsoleCommand::Seed(_));
+        let output = match command {
+            ConsoleCommand::Help => console::HELP.to_string(),
+            ConsoleCommand::Spawn(count) => {
+                self.game.spawn_enemies(count);
+                format!("spawned {} enemies", count)
+            }
+            ConsoleCommand::Level(wave) => {
+                self.game.start_wave(wave);
+                format!("starting wave {}", wave.max(1))
+            }
+            ConsoleCommand::God => {
+                self.game.god = !self.game.god;
+                format!("god mode {}", if self.game.god { "on" } else { "off" })
+            }
+            ConsoleCommand::GiveBangs(count) => {
+                self.game.super_bang = self.game.super_bang.saturating_add(count);
+                format!("{} super bangs", self.game.super_bang)
+            }
+            ConsoleCommand::TimeScale(scale) => {
+                self.time_scale = scale;
+                format!("time scale {}", scale)
+            }
+            ConsoleCommand::Seed(None) => format!("seed {}", self.game.seed),
+            ConsoleCommand::Seed(Some(seed)) => {
+                // later restarts keep the seed too
+                self.seed = Some(seed);
+                self.restart(seed);
+                format!("started over with seed {}", seed)
+            }
+            ConsoleCommand::KillAll => {
+                let count = self.game.enemies.len();
+                self.game.kill_all();
+                format!("killed {} enemies", count)
+            }
+            ConsoleCommand::Set(key, value) => {
+                let mut config = self.game.config;
+                if let Some(value) = value {
+                    config.set(&key, &value)?;
+                    config.validate()?;
+                    self.game.set_config(config);
+                    self.tuned = true;
                 }
-            });
+                let (_, value) = config
+                    .values()
+                    .into_iter()
+                    .find(|(name, _)| *name == key)
+                    .ok_or_else(|| anyhow!("unknown game setting `{}`", key))?;
+                format!("{} = {}", key, value)
+            }
+        };
+        if cheat {
+            self.game.cheated = true;
         }
+        self.console.print(&output, Color::WHITE);
+        Ok(())
+    }
 
-        let ptr = self.enemies.as_mut_ptr();
-        let len = self.enemies.len();
-        unsafe {
-            for i in 0..len {
-                for j in (i+1)..len {
-                    let e1 = &mut *ptr.add(i);
-                    let e2 = &mut *ptr.add(j);
-                    if collide(e1, e2) {
-                        self.sound.play(SoundType::Explode);
-                        e1.deal_damage(&e2.vel, e2.radius);
-                        e2.deal_damage(&e1.vel, e1.radius);
-                        Particle::spawn_particles(
-                            &mut self.particles,
-                            50,
-                            500.0,
-                            e1.color,
-                            e1.pos,
-                        );
-                    }
+    fn poll_gamepads(&mut self, helper: &mut WindowHelper<()>) {
+        for event in self.gamepads.poll() {
+            match event {
+                PadEvent::Nav(nav) if self.menu.is_open() => self.menu.ui.input.nav(nav),
+                PadEvent::Start if self.menu.screen() == Some(Screen::Pause) => {
+                    self.handle_menu_action(helper, MenuAction::Resume)
                 }
+                PadEvent::Start if !self.menu.is_open() => self.handle_action(Some(Action::TogglePause)),
+                _ => {}
             }
         }
-        
     }
 
-    fn draw(&mut self, graphics: &mut Graphics2D) {
-        graphics.draw_rectangle(&self.background_rect, self.background_color);
+    // Applies edits to the assets or the config file while the game runs. A
+    // file that fails to load is reported on screen until it is fixed, and the
+    // game keeps what it had loaded before.
+    fn hot_reload(&mut self, dt: f32) {
+        if let Some((_, time)) = &mut self.reload_status {
+            *time -= dt;
+            if *time <= 0.0 {
+                self.reload_status = None;
+            }
+        }
+        let changed = self.watcher.poll(dt);
+        if changed.is_empty() {
+            return;
+        }
 
-        self.player.draw(graphics);
+        // asset names are relative to the asset directory; after a failed
+        // reload any change is worth another try, as the fix may be in a file
+        // the broken manifest lists
+        let assets: Vec<String> = match self.assets.dir() {
+            Some(dir) => changed
+                .iter()
+                .filter_map(|path| path.strip_prefix(dir).ok())
+                .map(|name| name.to_string_lossy().replace('\\', "/"))
+                .collect(),
+            None => Vec::new(),
+        };
+        if !assets.is_empty() {
+            let retry = self.asset_error.is_some();
+            match self.game.sound.reload(&self.assets, |name| retry || assets.iter().any(|changed| changed == name)) {
+                Ok(true) => {
+                    self.reload_status = Some(("Reloaded sounds".to_string(), STATUS_TIME));
+                    self.asset_error = None;
+                }
+                Ok(false) => {}
+                Err(err) => self.asset_error = Some(format!("{:#}", err)),
+            }
+        }
 
-        for enemy in &self.enemies {
-            enemy.draw(graphics);
+        if changed.contains(&self.config_path) && self.config_path.exists() {
+            let config = cli::read_config(&self.config_path).and_then(|mut options| {
+                options.difficulty = self.game.difficulty;
+                let game_config = options.game_config()?;
+                Ok((options, game_config))
+            });
+            match config {
+                Ok((options, game_config)) => {
+                    let mut status = format!("Reloaded {}", self.config_path.display());
+                    self.config_error = None;
+                    let changes = game_config.changes_from(&self.file_config);
+                    self.file_config = game_config;
+                    // recordings and replays must run on the tuning they started with
+                    if !changes.is_empty() {
+                        if self.recorder.is_none() && self.replay.is_none() {
+                            let mut config = self.game.config;
+                            let applied = changes
+                                .iter()
+                                .try_for_each(|(key, value)| config.set(key, value))
+                                .and_then(|()| config.validate());
+                            match applied {
+                                Ok(()) => self.game.set_config(config),
+                                Err(err) => self.config_error = Some(format!("invalid [game] settings: {:#}", err)),
+                            }
+                        } else {
+                            status.push_str("; gameplay settings not applied while recording or replaying");
+                        }
+                    }
+                    self.reload_status = Some((status, STATUS_TIME));
+                    self.menu.settings = Settings::from_options(&options);
+                    self.menu.keys = options.keys;
+                    self.apply_settings();
+                }
+                Err(err) => self.config_error = Some(format!("{:#}", err)),
+            }
         }
+    }
 
-        for bullet in &self.bullets {
-            bullet.draw(graphics);
+    fn draw_reload_status(&self, canvas: &mut Canvas, hud_size: Vec2) {
+        let error_color = Color::from_rgb(1.0, 0.3, 0.3);
+        let lines: Vec<(&str, Color)> = [&self.asset_error, &self.config_error]
+            .into_iter()
+            .flatten()
+            .flat_map(|error| error.lines().map(|line| (line, error_color)))
+            .chain(self.reload_status.iter().map(|(status, _)| (status.as_str(), Color::GRAY)))
+            .collect();
+        let mut y = hud_size.y - 20.0 - lines.len() as f32 * 22.0;
+        for (line, color) in lines {
+            let text = self.font.layout_text(line, canvas.font_size(18.0), TextOptions::new());
+            canvas.draw_text(Vec2::new(20.0, y), color, &text);
+            y += 22.0;
         }
+    }
 
-        for particle in &self.particles {
-            particle.draw(graphics);
+    fn draw(&mut self, canvas: &mut Canvas) {
+        canvas.view = self.camera.world_view(self.window_size, true);
+        self.draw_background(canvas);
+        render::draw_world(canvas, &Scene::from(&self.game), self.effects);
+        if self.show_debug {
+            debug::draw_world(canvas, &self.game);
         }
 
-        self.display_text(
-            graphics,
-            &format!("Level: {}, Health: {}", self.level, self.player.radius).as_str(),
-            Vec2::new(20.0, 50.0),
-        );
-        self.display_text(
-            graphics,
-            &format!("Super Bangs: {}, Charged: {}", self.super_bang, self.charged_super_bang).as_str(),
-            Vec2::new(20.0, 90.0),
-        );
-        self.display_text(
-            graphics,
-            &format!("Hit: {}, Wasted: {}", self.bullets_hit, self.bullets_fired - self.bullets_hit).as_str(),
-            Vec2::new(20.0, 130.0),
-        );
+        canvas.view = self.camera.hud_view(self.window_size);
+        let scene = Scene::from(&self.game);
+        radar::draw_indicators(canvas, &self.camera, self.camera.hud_size(), &scene);
+        if self.menu.settings.minimap {
+            radar::draw_minimap(canvas, &self.camera, self.camera.hud_size(), &scene);
+        }
+        self.hud.draw(canvas, &self.font, &self.game, self.camera.hud_size());
     }
 }
 
 impl WindowHandler for MyWindowHandler {
-    fn on_start(&mut self, helper: &mut WindowHelper<()>, _info: WindowStartupInfo) {
+    fn on_start(&mut self, helper: &mut WindowHelper<()>, info: WindowStartupInfo) {
         helper.set_title("Color Bang!");
+        self.window_size = *info.viewport_size_pixels();
         self.frame_time();
     }
 
+    // The view is scaled to fit the new size; the bars around it are cleared
+    // once here because the translucent background only covers the view.
+    fn on_resize(&mut self, _helper: &mut WindowHelper<()>, size_pixels: UVec2) {
+        self.window_size = size_pixels;
+        self.needs_clear = true;
+    }
+
+    fn on_scale_factor_changed(&mut self, _helper: &mut WindowHelper<()>, _scale_factor: f64) {
+        self.needs_clear = true;
+    }
+
     fn on_draw(&mut self, helper: &mut WindowHelper, graphics: &mut Graphics2D) {
-        let dt = self.frame_time();
+        let mut dt = self.frame_time() * self.time_scale;
+        self.hot_reload(dt);
+        self.update_aim();
+        self.poll_gamepads(helper);
+
+        // pausing or losing brings up the menu; replays just play on
+        if self.replay.is_none() && !self.menu.is_open() {
+            if self.game.is_over() {
+                self.menu.open(Screen::GameOver);
+            } else if self.game.paused {
+                self.menu.open(Screen::Pause);
+            }
+        }
+        self.update_cursor(helper);
+
+        if let Some(replay) = self.replay.as_mut().filter(|_| !self.menu.is_open()) {
+            let mut actions = Vec::new();
+            match replay.next_frame(&mut actions) {
+                Some(replay_dt) => {
+                    dt = replay_dt;
+                    for action in actions {
+                        self.game.handle_action(action);
+                    }
+                }
+                None => self.replay_finished = true,
+            }
+        }
 
-        // spawn new wave of enemies if there are none
-        if self.enemies.len() == 0 {
-            self.sound.play(SoundType::Wave);
-            Enemy::spawn_n(&mut self.enemies, 1 * self.level, &self.player.pos);
-            self.super_bang = self.super_bang + 4 * self.level;
-            self.level += 1;
+        // the game is frozen while a menu is open
+        if !self.replay_finished && !self.menu.is_open() {
+            self.game.tick(dt);
+            if let Some(recorder) = &mut self.recorder {
+                recorder.tick(dt);
+            }
+            if !self.game.paused {
+                self.camera.update(dt, self.game.player.pos, &self.game.events);
+                self.clips.record(dt, &self.game);
+                self.hud.update(dt, &self.game);
+            }
         }
 
-        // charge super bang
-        if self.charging && self.super_bang > 0 {
-            self.super_bang -= 1;
-            self.charged_super_bang += 10;
-            self.sound.play(SoundType::Load);
+        // sound keeps running while the game is frozen, so menu clicks play
+        // and the music can still crossfade
+        self.game.sound.set_intensity(self.game.intensity());
+        self.game.sound.update();
+
+        if self.needs_clear {
+            graphics.clear_screen(Color::BLACK);
+            self.needs_clear = false;
         }
 
-        // fire bullets
-        if self.firing {
-            if self.firing_cooldown > 0.0 {  // prevent firing while cooling down
-                self.firing_cooldown -= dt;
+        let hud_size = self.camera.hud_size();
+        // moved out for the frame so the canvas can hold it while `self` draws
+        let mut batch = std::mem::take(&mut self.batch);
+        let mut canvas = Canvas::with_batch(graphics, self.camera.hud_view(self.window_size), &mut batch);
+        canvas.clip_to(hud_size);
+
+        // the smear keeps fading while paused, leaving only the text; trails
+        // are frozen with everything else, so the world can stay on screen
+        if self.game.paused {
+            if self.effects == EffectsStyle::Trails {
+                self.draw(&mut canvas);
             } else {
-                self.firing_cooldown += COOLDOWN_RATE; // update cooldown for firing
-                self.sound.play(SoundType::Fire);
+                canvas.view = self.camera.world_view(self.window_size, true);
+                self.draw_background(&mut canvas);
+                canvas.view = self.camera.hud_view(self.window_size);
+            }
+            if self.replay.is_some() {
+                self.display_text(
+                    &mut canvas,
+                    "PAUSED",
+                    Vec2::new(hud_size.x / 2.0 - 80.0, hud_size.y / 2.0),
+                );
+            }
+        } else {
+            self.draw(&mut canvas);
 
-                // use self.player.angle to calculate the bullet velocity
-                let (sin, cos) = self.player.angle.sin_cos();
-                let vel = Vec2::new(cos, sin) * 200.0; // bullet speed
+            if self.replay_finished {
+                self.display_text(
+                    &mut canvas,
+                    "REPLAY FINISHED",
+                    Vec2::new(hud_size.x / 2.0 - 200.0, hud_size.y / 2.0),
+                );
+            }
 
-                self.bullets.push(Bullet::new(self.player.pos, vel, 5.0));
-                self.bullets_fired += 1;
+            if let Some(spectate) = &mut self.spectate {
+                let game = &self.game;
+                spectate.update(dt, game.arena_size, &game.player, &game.enemies, &game.bullets);
             }
         }
 
-        if self.cursor_visible {
-            let dir = self.mouse_pos - self.player.pos;
-            let mut angle = dir.y.atan2(dir.x);
-            if angle < 0.0 {
-                angle += std::f32::consts::TAU; // TAU = 2*PI
-            }
-            self.player.angle = angle;
-        } else {
-            self.player.angle += self.player.rot * dt;
-            if self.player.angle < 0.0 {
-                self.player.angle += std::f32::consts::TAU; // TAU = 2*PI
-            } else if self.player.angle > std::f32::consts::TAU {
-                self.player.angle -= std::f32::consts::TAU; // TAU = 2*PI
-            }
+        let menu_action = self.menu.draw(&mut canvas, &self.font, hud_size, &self.game);
+        self.draw_reload_status(&mut canvas, hud_size);
+        if self.show_frame_time {
+            self.draw_frame_time(&mut canvas, hud_size);
+        }
+        if self.show_debug {
+            debug::draw_panel(&mut canvas, &self.font, hud_size, &self.game, &self.frame_times);
         }
+        if self.console.open {
+            self.console.draw(&mut canvas, &self.font, hud_size);
+        }
+        canvas.clear_clip();
+        drop(canvas);
+        batch.end_frame();
+        self.batch = batch;
 
-        if self.paused {
-            //self.draw(graphics);
-            graphics.draw_rectangle(&self.background_rect, self.background_color);
-            self.display_text(
-                graphics,
-                "PAUSED",
-                Vec2::new(WIDTH / 2.0 - 80.0, HEIGHT / 2.0),
-            );
-        } else {
-            self.update(dt);
-            self.draw(graphics);
+        if let Some(action) = menu_action {
+            self.handle_menu_action(helper, action);
+        }
+
+        if self.screenshot_requested {
+            self.screenshot_requested = false;
+            let capture = graphics.capture(ImageDataType::RGB);
+            render::save_screenshot(capture.data().clone(), capture.size(), &render::timestamped_path("png"));
         }
 
         helper.request_redraw();
     }
 
     fn on_mouse_move(&mut self, _helper: &mut WindowHelper<()>, position: Vec2) {
-        self.mouse_pos = position;
+        self.mouse_screen_pos = Some(position);
+        let hud_pos = self.camera.hud_view(self.window_size).to_world(position);
+        self.menu.ui.input.mouse_move(hud_pos);
+        self.update_aim();
+    }
+
+    fn on_mouse_wheel_scroll(&mut self, _helper: &mut WindowHelper<()>, distance: MouseScrollDistance) {
+        let lines = match distance {
+            MouseScrollDistance::Lines { y, .. } => y as f32,
+            MouseScrollDistance::Pixels { y, .. } => y as f32 / 50.0,
+            MouseScrollDistance::Pages { y, .. } => y as f32 * 5.0,
+        };
+        self.camera.zoom_by(1.1f32.powf(lines));
     }
 
     fn on_mouse_button_down(&mut self, _helper: &mut WindowHelper<()>, button: MouseButton) {
-        if let MouseButton::Left = button {
-            self.firing = true;
-        } else if let MouseButton::Right = button {
-            // self.sound.play(SoundType::Load);
-            self.charging = true;
-        } else if let MouseButton::Middle = button {
-            self.sound.play(SoundType::Wave);
-            Enemy::spawn_n(&mut self.enemies, 10, &self.player.pos);
+        // releases still go through so nothing stays held after the menu closes
+        if self.menu.is_open() {
+            if button == MouseButton::Left {
+                self.menu.ui.input.mouse_down();
+            }
+            return;
         }
+        self.handle_action(Action::from_mouse_down(button));
     }
 
     fn on_mouse_button_up(&mut self, _helper: &mut WindowHelper<()>, button: MouseButton) {
-        if let MouseButton::Left = button {
-            self.firing = false;
-            self.firing_cooldown = 0.0;
-        } else if let MouseButton::Middle = button {
-            //
-        } else if let MouseButton::Right = button {
-            self.charging = false;
-            self.sound.play(SoundType::MultiFire);
-
-            let num_bullets = std::cmp::max(10,self.charged_super_bang);
-            Bullet::super_bang(&mut self.bullets, num_bullets, self.player.pos);
-            self.charged_super_bang = 0;
-            self.bullets_fired += num_bullets;
+        if button == MouseButton::Left {
+            self.menu.ui.input.mouse_up();
         }
+        self.handle_action(Action::from_mouse_up(button));
     }
 
     fn on_key_down(
         &mut self,
-        _helper: &mut WindowHelper<()>,
+        helper: &mut WindowHelper<()>,
         virtual_key_code: Option<VirtualKeyCode>,
         _scancode: KeyScancode,
     ) {
-        match virtual_key_code {
-            Some(VirtualKeyCode::Backspace) => {
-                self.paused = !self.paused;
-            }
-            Some(VirtualKeyCode::Space) => {
-                self.firing = true;
-            }
-            Some(VirtualKeyCode::R) => {
-                self.charging = true;
-            }
-            Some(VirtualKeyCode::A) => {
-                self.player.rot = -5.0;
-            }
-            Some(VirtualKeyCode::D) => {
-                self.player.rot = 5.0;
-            }
-            Some(VirtualKeyCode::W) => {
-                self.player.speed = 100.0; // move forward
+        let Some(key) = virtual_key_code else {
+            return;
+        };
+        match key {
+            VirtualKeyCode::F2 if self.menu.screen() == Some(Screen::Settings) => self.menu.pop(),
+            VirtualKeyCode::F2 => self.menu.push(Screen::Settings),
+            VirtualKeyCode::F3 => self.show_debug = !self.show_debug,
+            VirtualKeyCode::F4 => self.batch.enabled = !self.batch.enabled,
+            VirtualKeyCode::F5 => self.show_frame_time = !self.show_frame_time,
+            VirtualKeyCode::F9 => self.clips.save(self.effects),
+            VirtualKeyCode::F12 => self.screenshot_requested = true,
+            VirtualKeyCode::Grave => self.console.toggle(),
+            _ if self.console.open => {
+                if let Some(command) = self.console.key_down(key) {
+                    if let Err(err) = self.run_command(command) {
+                        self.console.error(&err);
+                    }
+                }
+                self.swallow_key_up = Some(key);
             }
-            Some(VirtualKeyCode::S) => {
-                self.player.speed = -100.0; // move backward
+            _ if self.menu.is_open() => {
+                // the pause key also leaves the pause menu
+                let action = self.menu.keys.action_down(key);
+                if self.menu.screen() == Some(Screen::Pause) && action == Some(Action::TogglePause) {
+                    self.handle_menu_action(helper, MenuAction::Resume);
+                } else {
+                    self.menu.ui.input.key_down(key);
+                }
+                self.swallow_key_up = Some(key);
             }
-            _ => {}
+            VirtualKeyCode::Equals | VirtualKeyCode::NumpadAdd => self.camera.zoom_by(1.25),
+            VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract => self.camera.zoom_by(0.8),
+            _ => self.handle_action(self.menu.keys.action_down(key)),
+        }
+    }
+
+    fn on_keyboard_char(&mut self, _helper: &mut WindowHelper<()>, unicode_codepoint: char) {
+        if self.console.open {
+            self.console.char_typed(unicode_codepoint);
+        } else if self.menu.is_open() {
+            self.menu.ui.input.char_typed(unicode_codepoint);
         }
     }
 
@@ -374,37 +733,181 @@ impl WindowHandler for MyWindowHandler {
         virtual_key_code: Option<VirtualKeyCode>,
         _scancode: KeyScancode,
     ) {
-        match virtual_key_code {
-            Some(VirtualKeyCode::Escape) => {
-                self.cursor_visible = !self.cursor_visible;
-                _helper.set_cursor_visible(self.cursor_visible);
-                let _ = _helper.set_cursor_grab(!self.cursor_visible);
-            }
-            Some(VirtualKeyCode::Space) => {
-                self.firing = false;
-                self.firing_cooldown = 0.0;
-            }
-            Some(VirtualKeyCode::R) => {
-                self.charging = false;
-                self.sound.play(SoundType::MultiFire);
-
-                let num_bullets = std::cmp::max(10,self.charged_super_bang);
-                Bullet::super_bang(&mut self.bullets, num_bullets, self.player.pos);
-                self.charged_super_bang = 0;
-                self.bullets_fired += num_bullets;
-            }
-            Some(VirtualKeyCode::A) => {
-                self.player.rot = 0.0;
+        let Some(key) = virtual_key_code else {
+            return;
+        };
+        if self.swallow_key_up == Some(key) {
+            self.swallow_key_up = None;
+            return;
+        }
+        self.handle_action(self.menu.keys.action_up(key));
+    }
+}
+
+fn run_headless(mut game: Game, mut recorder: Option<Recorder>, replay: Option<Replay>, ticks: Option<u32>) {
+    let mut frames = 0;
+    if let Some(mut replay) = replay {
+        let mut actions = Vec::new();
+        while let Some(dt) = replay.next_frame(&mut actions) {
+            for action in actions.drain(..) {
+                game.handle_action(action);
             }
-            Some(VirtualKeyCode::D) => {
-                self.player.rot = 0.0;
+            game.tick(dt);
+            frames += 1;
+        }
+    } else {
+        for _ in 0..ticks.unwrap_or(0) {
+            game.tick(HEADLESS_DT);
+            if let Some(recorder) = &mut recorder {
+                recorder.tick(HEADLESS_DT);
             }
-            _ => {}
+            frames += 1;
         }
     }
+
+    println!(
+        "Simulated {} frames: level {}, score {}, health {}, hit {}, wasted {}, enemies left {}",
+        frames,
+        game.level,
+        game.score,
+        game.player.radius,
+        game.bullets_hit,
+        game.bullets_fired - game.bullets_hit,
+        game.enemies.len()
+    );
+}
+
+// Simulates up to `tick` (from the start of a replay, or without any input)
+// and renders that frame to a PNG file instead of opening a window.
+fn run_render_frame(mut game: Game, replay: Option<Replay>, tick: u32, out: &Path, options: &Options) -> Result<()> {
+    let mut actions = Vec::new();
+    let mut replay = replay;
+    for frame in 0..tick {
+        let dt = match &mut replay {
+            Some(replay) => replay
+                .next_frame(&mut actions)
+                .ok_or_else(|| anyhow!("the replay ends after {} frames", frame))?,
+            None => HEADLESS_DT,
+        };
+        for action in actions.drain(..) {
+            game.handle_action(action);
+        }
+        game.tick(dt);
+    }
+
+    let size = UVec2::new(options.width, options.height);
+    render::render_frame(&Scene::from(&game), options.effects, size).save_png(out)?;
+    println!("Rendered frame {} to {} ({}x{})", tick, out.display(), size.x, size.y);
+    Ok(())
+}
+
+fn create_window(options: WindowCreationOptions) -> Result<Window> {
+    Window::new_with_options("Color Bang!", options)
+        .map_err(|err| anyhow!("failed to create window: {:?}", err.error()))
+}
+
+fn run(options: Options) -> Result<()> {
+    let window_options = if options.fullscreen {
+        WindowCreationOptions::new_fullscreen_borderless()
+    } else {
+        WindowCreationOptions::new_windowed(
+            WindowSize::ScaledPixels(Vec2::new(options.width as f32, options.height as f32)),
+            Some(WindowPosition::Center),
+        )
+    }
+    .with_vsync(options.vsync);
+
+    let assets = Assets::new(options.assets.clone())?;
+
+    if let Some(addr) = &options.watch {
+        let handler = SpectatorWindowHandler::connect(addr, assets.load_font()?)?;
+        let window = create_window(window_options)?;
+        window.run_loop(handler);
+    }
+
+    let replay = match &options.replay {
+        Some(path) => Some(Replay::load(path)?),
+        None => None,
+    };
+    let header = match &replay {
+        Some(replay) => replay.header,
+        None => ReplayHeader {
+            seed: options.seed.unwrap_or_else(rand::random),
+            level: options.level,
+            difficulty: options.difficulty,
+            arena_size: Vec2::new(
+                options.arena_width.unwrap_or(ARENA_WIDTH),
+                options.arena_height.unwrap_or(ARENA_HEIGHT),
+            ),
+            config: options.game_config()?,
+        },
+    };
+    let recorder = match &options.record {
+        Some(path) => Some(Recorder::create(path, header)?),
+        None => None,
+    };
+
+    let offscreen = options.render_frame.is_some();
+    let mut sound = SoundPlayer::new(options.mute || options.headless || offscreen, &assets);
+    sound.set_settings(options.audio);
+    let mut game = Game::new(
+        header.seed,
+        header.level,
+        header.difficulty,
+        header.config,
+        header.arena_size,
+        sound,
+    );
+    game.set_enemy_style(options.enemy_style);
+
+    if options.headless {
+        run_headless(game, recorder, replay, options.ticks);
+        return Ok(());
+    }
+    if let (Some(tick), Some(out)) = (options.render_frame, &options.out) {
+        // particles too, so the same frame always renders the same image
+        rng::seed_cosmetic(header.seed);
+        return run_render_frame(game, replay, tick, out, &options);
+    }
+
+    let spectate = match options.spectate {
+        Some(port) => Some(SpectateServer::start(port, options.spectate_public)?),
+        None => None,
+    };
+    let camera = Camera::new(header.arena_size, options.camera_follow, options.screen_shake);
+    // starting over would break a recording, and replays play as recorded
+    let can_restart = recorder.is_none() && replay.is_none();
+    let mut menu = Menu::new(Settings::from_options(&options), options.keys, &options.config_path(), can_restart);
+    if replay.is_none() {
+        menu.open(Screen::Title);
+    }
+    let mut handler = MyWindowHandler::new(assets, game, camera, menu, spectate, recorder, replay)?;
+    handler.seed = options.seed;
+    handler.start_level = header.level;
+    handler.apply_settings();
+    handler.clips = ClipRecorder::new(options.clip_length, options.clip_format);
+    let window = create_window(window_options)?;
+    window.run_loop(handler);
 }
 
 fn main() {
-    let window = Window::new_centered("Title", (WIDTH as u32, HEIGHT as u32)).unwrap();
-    window.run_loop(MyWindowHandler::new());
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let result = match cli::parse(&args) {
+        Ok(Command::Help) => {
+            print!("{}", cli::HELP);
+            return;
+        }
+        Ok(Command::Run(options)) => run(*options),
+        Ok(Command::RenderSound { preset, out, assets }) => {
+            Assets::new(assets).and_then(|assets| synth::render_preset(&assets, &preset, &out))
+        }
+        Err(err) => Err(err),
+    };
+
+    if let Err(err) = result {
+        eprintln!("error: {:#}", err);
+        eprintln!("Run with --help for usage.");
+        std::process::exit(1);
+    }
 }
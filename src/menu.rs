@@ -0,0 +1,317 @@
+use std::path::{Path, PathBuf};
+
+use speedy2d::color::Color;
+use speedy2d::dimen::Vec2;
+use speedy2d::font::Font;
+use speedy2d::window::VirtualKeyCode;
+
+use crate::canvas::Canvas;
+use crate::game::Game;
+use crate::ini;
+use crate::input::{self, Control, KeyBindings};
+use crate::scores::{self, HighScore, HighScores};
+use crate::settings::Settings;
+use crate::ui::{Panel, Ui};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Screen {
+    Title,
+    Pause,
+    Settings,
+    Controls,
+    HighScores,
+    GameOver,
+}
+
+// What the game has to do after a frame of the menus.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MenuAction {
+    // close the menu and carry on, unpausing if paused
+    Resume,
+    Restart,
+    Quit,
+    // the settings were changed and should be applied
+    ApplySettings,
+}
+
+// The menu screens, shown one at a time over the game. Screens opened from
+// another one go back to it when they are left.
+pub struct Menu {
+    pub ui: Ui,
+    pub settings: Settings,
+    pub keys: KeyBindings,
+    stack: Vec<Screen>,
+    scores: HighScores,
+    config_path: PathBuf,
+    // restarting is left out while recording or replaying
+    can_restart: bool,
+    settings_changed: bool,
+    rebinding: Rebinding,
+    name: String,
+    score_saved: bool,
+}
+
+fn accuracy(game: &Game) -> String {
+    match game.bullets_fired {
+        0 => "-".to_string(),
+        fired => format!("{:.0}%", game.bullets_hit as f32 / fired as f32 * 100.0),
+    }
+}
+
+impl Menu {
+    pub fn new(settings: Settings, keys: KeyBindings, config_path: &Path, can_restart: bool) -> Self {
+        Menu {
+            ui: Ui::default(),
+            settings,
+            keys,
+            stack: Vec::new(),
+            scores: HighScores::load(HighScores::path_for(config_path)),
+            config_path: config_path.to_path_buf(),
+            can_restart,
+            settings_changed: false,
+            rebinding: Rebinding::default(),
+            name: String::new(),
+            score_saved: false,
+        }
+    }
+
+    pub fn config_path(&self) -> &Path {
+        &self.config_path
+    }
+
+    pub fn screen(&self) -> Option<Screen> {
+        self.stack.last().copied()
+    }
+
+    pub fn is_open(&self) -> bool {
+        !self.stack.is_empty()
+    }
+
+    // Shows a screen on its own, closing any others.
+    pub fn open(&mut self, screen: Screen) {
+        self.close();
+        self.push(screen);
+    }
+
+    // Shows a screen that goes back to the current one when left.
+    pub fn push(&mut self, screen: Screen) {
+        if screen == Screen::GameOver {
+            self.score_saved = false;
+        }
+        self.stack.push(screen);
+        self.ui.reset_focus();
+    }
+
+    // Leaves the current screen, saving what was changed on it.
+    pub fn pop(&mut self) {
+        match self.stack.pop() {
+            Some(Screen::Settings) if self.settings_changed => {
+                self.settings_changed = false;
+                if let Err(err) = self.settings.save(&self.config_path) {
+                    eprintln!("warning: could not save settings: {:#}", err);
+                }
+            }
+            Some(Screen::Controls) => {
+                self.rebinding.control = None;
+                if self.rebinding.changed {
+                    self.rebinding.changed = false;
+                    if let Err(err) = ini::update(&self.config_path, "keys", &self.keys.values()) {
+                        eprintln!("warning: could not save key bindings: {:#}", err);
+                    }
+                }
+            }
+            _ => {}
+        }
+        self.ui.reset_focus();
+    }
+
+    pub fn close(&mut self) {
+        while self.is_open() {
+            self.pop();
+        }
+    }
+
+    pub fn draw(&mut self, canvas: &mut Canvas, font: &Font, hud_size: Vec2, game: &Game) -> Option<MenuAction> {
+        let screen = self.screen()?;
+        // capture the key before the panel turns it into navigation
+        let key = self.rebinding.control.and_then(|_| self.ui.input.take_key());
+
+        let mut panel = self.ui.panel(canvas, font, hud_size);
+        let mut next = None;
+        let mut back = panel.back();
+        let action = match screen {
+            Screen::Title => {
+                panel.title("COLOR BANG!");
+                let mut action = None;
+                if panel.button("Play") {
+                    action = Some(MenuAction::Resume);
+                }
+                Self::common_buttons(&mut panel, &mut next);
+                if panel.button("High scores") {
+                    next = Some(Screen::HighScores);
+                }
+                if panel.button("Quit") {
+                    action = Some(MenuAction::Quit);
+                }
+                // there is nothing to go back to
+                back = false;
+                action
+            }
+            Screen::Pause => {
+                panel.title("PAUSED");
+                let mut action = None;
+                if panel.button("Resume") || back {
+                    action = Some(MenuAction::Resume);
+                }
+                if self.can_restart && panel.button("Restart") {
+                    action = Some(MenuAction::Restart);
+                }
+                Self::common_buttons(&mut panel, &mut next);
+                if panel.button("Quit") {
+                    action = Some(MenuAction::Quit);
+                }
+                back = false;
+                action
+            }
+            Screen::Settings => {
+                panel.title("SETTINGS");
+                let changed = self.settings.edit(&mut panel);
+                self.settings_changed |= changed;
+                panel.space();
+                back |= panel.button("Back");
+                changed.then_some(MenuAction::ApplySettings)
+            }
+            Screen::Controls => {
+                panel.title("CONTROLS");
+                self.rebinding.edit(&mut panel, &mut self.keys, key);
+                panel.space();
+                back |= self.rebinding.control.is_none() && panel.button("Back");
+                None
+            }
+            Screen::HighScores => {
+                panel.title("HIGH SCORES");
+                if self.scores.entries.is_empty() {
+                    panel.label("No scores yet", Color::GRAY);
+                }
+                for (rank, entry) in self.scores.entries.iter().enumerate() {
+                    panel.row(
+                        &format!("{:>2}. {}", rank + 1, entry.name),
+                        &format!("{}  wave {}  {}", entry.score, entry.wave, entry.difficulty.name()),
+                        Color::WHITE,
+                    );
+                }
+                panel.space();
+                back |= panel.button("Back");
+                None
+            }
+            Screen::GameOver => {
+                panel.title("GAME OVER");
+                panel.row("Score", &game.score.to_string(), Color::WHITE);
+                panel.row("Wave", &game.level.saturating_sub(1).to_string(), Color::WHITE);
+                panel.row("Accuracy", &accuracy(game), Color::WHITE);
+                panel.space();
+                if game.cheated {
+                    panel.label("No high score: console cheats were used", Color::GRAY);
+                } else if !self.score_saved && self.scores.qualifies(game.score) {
+                    panel.label("A new high score!", Color::YELLOW);
+                    let submitted = panel.text_input("Your name", &mut self.name, scores::MAX_NAME_LENGTH);
+                    if panel.button("Save score") || submitted {
+                        let name = self.name.trim();
+                        self.scores.add(HighScore {
+                            score: game.score,
+                            wave: game.level.saturating_sub(1),
+                            difficulty: game.difficulty,
+                            name: if name.is_empty() { "???".to_string() } else { name.to_string() },
+                        });
+                        self.score_saved = true;
+                        next = Some(Screen::HighScores);
+                    }
+                }
+                let mut action = None;
+                if self.can_restart && panel.button("Play again") {
+                    action = Some(MenuAction::Restart);
+                }
+                if panel.button("High scores") {
+                    next = Some(Screen::HighScores);
+                }
+                if panel.button("Quit") {
+                    action = Some(MenuAction::Quit);
+                }
+                back = false;
+                action
+            }
+        };
+        drop(panel);
+
+        if let Some(screen) = next {
+            self.push(screen);
+        } else if back {
+            self.pop();
+            if !self.is_open() {
+                return Some(MenuAction::Resume);
+            }
+        }
+        action
+    }
+
+    fn common_buttons(panel: &mut Panel, next: &mut Option<Screen>) {
+        if panel.button("Settings") {
+            *next = Some(Screen::Settings);
+        }
+        if panel.button("Controls") {
+            *next = Some(Screen::Controls);
+        }
+    }
+}
+
+// The controls screen, which changes a key binding by waiting for the next
+// key pressed.
+#[derive(Default)]
+struct Rebinding {
+    control: Option<Control>,
+    error: Option<String>,
+    changed: bool,
+}
+
+impl Rebinding {
+    fn edit(&mut self, panel: &mut Panel, keys: &mut KeyBindings, key: Option<VirtualKeyCode>) {
+        if let Some(control) = self.control {
+            match key {
+                Some(VirtualKeyCode::Escape) => self.control = None,
+                Some(key) => match input::key_name(key) {
+                    Some(_) => {
+                        keys.set(control, key);
+                        self.changed = true;
+                        self.control = None;
+                        self.error = None;
+                    }
+                    None => self.error = Some(format!("{:?} can't be bound", key)),
+                },
+                None => {}
+            }
+        }
+
+        for control in Control::ALL {
+            let value = match self.control {
+                Some(rebinding) if rebinding == control => "press a key".to_string(),
+                _ => input::key_name(keys.key(control)).unwrap_or("?").replace('_', " "),
+            };
+            if panel.button_with_value(control.label(), &value) && self.control.is_none() {
+                self.control = Some(control);
+                self.error = None;
+            }
+        }
+        panel.row("Aim and fire", "mouse", Color::GRAY);
+        panel.row("Show or hide the cursor", "escape", Color::GRAY);
+        if self.control.is_none() && panel.button("Reset to defaults") {
+            *keys = KeyBindings::default();
+            self.changed = true;
+        }
+
+        match (&self.error, self.control) {
+            (Some(error), _) => panel.hint(error),
+            (None, Some(_)) => panel.hint("Press the new key, or Escape to cancel"),
+            (None, None) => panel.hint("Select a control to change its key"),
+        }
+    }
+}
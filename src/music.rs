@@ -0,0 +1,333 @@
+use std::borrow::Cow;
+use std::io::Cursor;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use kira::sound::static_sound::StaticSoundData;
+use kira::sound::streaming::{StreamingSoundData, StreamingSoundHandle};
+use kira::sound::{FromFileError, PlaybackState};
+use kira::track::TrackHandle;
+use kira::{Decibels, Tween};
+
+use crate::assets::Assets;
+use crate::ini;
+
+pub const MANIFEST_FILE: &str = "music.ini";
+
+const LAYER_FADE: Duration = Duration::from_millis(1500);
+
+// One piece of the playlist: a base recording that always plays, and stems
+// of the same length that fade in once the intensity reaches their threshold.
+struct Piece {
+    base: String,
+    layers: Vec<(String, f32)>,
+    volume: f32, // decibels
+}
+
+struct Layer {
+    handle: StreamingSoundHandle<FromFileError>,
+    threshold: f32,
+    audible: bool,
+}
+
+struct Playing {
+    base: StreamingSoundHandle<FromFileError>,
+    layers: Vec<Layer>,
+    duration: f64,
+    volume: f32,
+}
+
+impl Playing {
+    fn stop(&mut self, tween: Tween) {
+        self.base.stop(tween);
+        for layer in &mut self.layers {
+            layer.handle.stop(tween);
+        }
+    }
+}
+
+// Streams the music from `music.ini`, crossfading from one piece to the next
+// and fading intensity layers in and out as the game heats up.
+pub struct MusicPlayer {
+    assets: Assets,
+    pieces: Vec<Piece>,
+    shuffle: bool,
+    crossfade: f32,
+    stinger: Option<StaticSoundData>,
+    files: Vec<String>, // the manifest and every file it names
+    playing: Option<Playing>,
+    current: usize,
+    intensity: f32,
+}
+
+type Stream = StreamingSoundData<FromFileError>;
+
+// The compressed file is kept in memory and decoded while it plays.
+fn open_stream(assets: &Assets, name: &str) -> Result<Stream> {
+    let bytes: Cow<'static, [u8]> = assets.read(name)?;
+    StreamingSoundData::from_cursor(Cursor::new(bytes))
+        .map_err(|err| anyhow!("could not open {}: {}", assets.describe(name), err))
+}
+
+fn fade(duration: f32) -> Tween {
+    Tween {
+        duration: Duration::from_secs_f32(duration),
+        ..Default::default()
+    }
+}
+
+impl MusicPlayer {
+    // Returns `None` when there is no music manifest, which simply means the
+    // game has no music. None is shipped or embedded: music is opt-in, added
+    // by putting a manifest in the override directory. Like the sound manifest, every problem in it is
+    // reported at once.
+    pub fn load(assets: &Assets) -> Result<Option<Self>> {
+        if !assets.exists(MANIFEST_FILE) {
+            return Ok(None);
+        }
+        let manifest = assets.describe(MANIFEST_FILE);
+        let entries = ini::parse(&assets.read_to_string(MANIFEST_FILE)?)
+            .with_context(|| format!("parsing {}", manifest))?;
+
+        let mut player = MusicPlayer {
+            assets: assets.clone(),
+            pieces: Vec::new(),
+            shuffle: false,
+            crossfade: 3.0,
+            stinger: None,
+            files: vec![MANIFEST_FILE.to_string()],
+            playing: None,
+            current: 0,
+            intensity: 0.0,
+        };
+        let mut errors = Vec::new();
+        let mut playlist: Vec<(String, usize)> = Vec::new();
+
+        for entry in entries.iter().filter(|entry| entry.section == "playlist") {
+            let line = entry.line;
+            match entry.key.as_str() {
+                "tracks" => {
+                    playlist = entry
+                        .value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|name| !name.is_empty())
+                        .map(|name| (name.to_string(), line))
+                        .collect()
+                }
+                "shuffle" => match entry.value.as_str() {
+                    "true" | "yes" | "on" | "1" => player.shuffle = true,
+                    "false" | "no" | "off" | "0" => player.shuffle = false,
+                    value => errors.push(format!("line {}: invalid shuffle `{}`", line, value)),
+                },
+                "crossfade" => match entry.value.parse::<f32>() {
+                    Ok(crossfade) if crossfade > 0.0 => player.crossfade = crossfade,
+                    _ => errors.push(format!("line {}: invalid crossfade `{}`", line, entry.value)),
+                },
+                "stinger" => {
+                    player.files.push(entry.value.clone());
+                    let stinger = assets.read(&entry.value).and_then(|bytes| {
+                        StaticSoundData::from_cursor(Cursor::new(bytes)).map_err(|err| anyhow!("{}", err))
+                    });
+                    match stinger {
+                        Ok(data) => player.stinger = Some(data),
+                        Err(err) => errors.push(format!(
+                            "line {}: could not load {}: {:#}",
+                            line,
+                            assets.describe(&entry.value),
+                            err
+                        )),
+                    }
+                }
+                key => errors.push(format!("line {}: unknown key `{}` in [playlist]", line, key)),
+            }
+        }
+
+        for (name, line) in &playlist {
+            let section = format!("track.{}", name);
+            let mut piece = Piece {
+                base: String::new(),
+                layers: Vec::new(),
+                volume: 0.0,
+            };
+
+            for entry in entries.iter().filter(|entry| entry.section == section) {
+                let line = entry.line;
+                match entry.key.as_str() {
+                    "file" => piece.base = entry.value.clone(),
+                    "layers" => {
+                        for spec in entry.value.split(',').filter(|spec| !spec.trim().is_empty()) {
+                            match spec.split_once('@').map(|(file, at)| (file.trim(), at.trim().parse::<f32>())) {
+                                Some((file, Ok(threshold))) if (0.0..=1.0).contains(&threshold) => {
+                                    piece.layers.push((file.to_string(), threshold))
+                                }
+                                _ => errors.push(format!(
+                                    "line {}: invalid layer `{}`, expected `file.ogg@0.5`",
+                                    line,
+                                    spec.trim()
+                                )),
+                            }
+                        }
+                    }
+                    "volume" => match entry.value.parse() {
+                        Ok(volume) => piece.volume = volume,
+                        Err(_) => errors.push(format!("line {}: invalid volume `{}`", line, entry.value)),
+                    },
+                    key => errors.push(format!("line {}: unknown key `{}` in [{}]", line, key, section)),
+                }
+            }
+
+            if piece.base.is_empty() {
+                errors.push(format!("line {}: [{}] is missing or has no file", line, section));
+                continue;
+            }
+            let files = std::iter::once(&piece.base).chain(piece.layers.iter().map(|(file, _)| file));
+            for file in files {
+                player.files.push(file.clone());
+                match open_stream(assets, file) {
+                    Ok(data) if *file == piece.base && data.duration().as_secs_f32() < player.crossfade => {
+                        errors.push(format!(
+                            "[{}]: {} is shorter than the crossfade of {} seconds",
+                            section, file, player.crossfade
+                        ))
+                    }
+                    Ok(_) => {}
+                    Err(err) => errors.push(format!("[{}]: {:#}", section, err)),
+                }
+            }
+            player.pieces.push(piece);
+        }
+
+        let mut unknown: Vec<&str> = Vec::new();
+        for entry in &entries {
+            let listed = entry.section == "playlist"
+                || entry
+                    .section
+                    .strip_prefix("track.")
+                    .is_some_and(|name| playlist.iter().any(|(listed, _)| listed == name));
+            if !listed && !unknown.contains(&entry.section.as_str()) {
+                unknown.push(&entry.section);
+                errors.push(format!(
+                    "line {}: [{}] is not a track in the playlist",
+                    entry.line, entry.section
+                ));
+            }
+        }
+
+        if !errors.is_empty() {
+            bail!("invalid music manifest {}:\n  {}", manifest, errors.join("\n  "));
+        }
+        if player.shuffle && !player.pieces.is_empty() {
+            player.current = rand::random_range(0..player.pieces.len());
+        }
+        Ok(Some(player))
+    }
+
+    // The manifest and every file it names.
+    pub fn files(&self) -> impl Iterator<Item = &str> {
+        self.files.iter().map(String::as_str)
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(mut playing) = self.playing.take() {
+            playing.stop(fade(self.crossfade_for(playing.duration)));
+        }
+    }
+
+    // Pieces shorter than two crossfades fade for half their length, so a
+    // piece never starts out already finishing.
+    fn crossfade_for(&self, duration: f64) -> f32 {
+        self.crossfade.min((duration / 2.0) as f32)
+    }
+
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.intensity = intensity;
+    }
+
+    // Plays the wave stinger, if the manifest has one.
+    pub fn play_stinger(&mut self, track: &mut TrackHandle) -> bool {
+        match &self.stinger {
+            Some(stinger) => {
+                let _ = track.play(stinger.clone());
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn update(&mut self, track: &mut TrackHandle) {
+        if self.pieces.is_empty() {
+            return;
+        }
+
+        let finishing = match &self.playing {
+            Some(playing) => {
+                playing.base.state() == PlaybackState::Stopped
+                    || playing.base.position() >= playing.duration - self.crossfade_for(playing.duration) as f64
+            }
+            None => true,
+        };
+        if finishing {
+            let first = self.playing.is_none();
+            if let Some(mut playing) = self.playing.take() {
+                playing.stop(fade(self.crossfade_for(playing.duration)));
+                self.advance();
+            }
+            if let Err(err) = self.start(track, !first) {
+                eprintln!("warning: music disabled: {:#}", err);
+                self.pieces.clear();
+            }
+            return;
+        }
+
+        let intensity = self.intensity;
+        if let Some(playing) = &mut self.playing {
+            for layer in &mut playing.layers {
+                let audible = intensity >= layer.threshold;
+                if audible != layer.audible {
+                    layer.audible = audible;
+                    let volume = if audible { Decibels(playing.volume) } else { Decibels::SILENCE };
+                    layer.handle.set_volume(volume, fade(LAYER_FADE.as_secs_f32()));
+                }
+            }
+        }
+    }
+
+    fn advance(&mut self) {
+        let count = self.pieces.len();
+        self.current = if self.shuffle && count > 1 {
+            // any piece but the one that just played
+            (self.current + 1 + rand::random_range(0..count - 1)) % count
+        } else {
+            (self.current + 1) % count
+        };
+    }
+
+    fn start(&mut self, track: &mut TrackHandle, crossfade: bool) -> Result<()> {
+        let piece = &self.pieces[self.current];
+        let data = open_stream(&self.assets, &piece.base)?;
+        let duration = data.duration().as_secs_f64();
+        let fade_in = crossfade.then(|| fade(self.crossfade_for(duration)));
+        let base = track.play(data.volume(piece.volume).fade_in_tween(fade_in))?;
+
+        let mut layers = Vec::new();
+        for (file, threshold) in &piece.layers {
+            let audible = self.intensity >= *threshold;
+            let volume = if audible { Decibels(piece.volume) } else { Decibels::SILENCE };
+            let handle = track.play(open_stream(&self.assets, file)?.volume(volume).fade_in_tween(fade_in))?;
+            layers.push(Layer {
+                handle,
+                threshold: *threshold,
+                audible,
+            });
+        }
+
+        self.playing = Some(Playing {
+            base,
+            layers,
+            duration,
+            volume: piece.volume,
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,247 @@
+use speedy2d::color::Color;
+
+// Enemies fall into this many kinds by the hue of their random color, and a
+// palette has a color for each kind.
+const KINDS: usize = 6;
+
+// The least contrast an enemy color has against the black background, as the
+// WCAG ratio of relative luminances. 3:1 is the minimum for graphics.
+const MIN_CONTRAST: f32 = 3.0;
+
+const fn rgb(hex: u32) -> Color {
+    Color::from_rgb(
+        ((hex >> 16) & 0xff) as f32 / 255.0,
+        ((hex >> 8) & 0xff) as f32 / 255.0,
+        (hex & 0xff) as f32 / 255.0,
+    )
+}
+
+// Colors each type of color vision tells apart. The first two are based on
+// the Okabe-Ito palette, leaving out the reds protanopes see as dark.
+const DEUTERANOPIA: [Color; KINDS] = [
+    rgb(0xe69f00), // orange
+    rgb(0x56b4e9), // sky blue
+    rgb(0xf0e442), // yellow
+    rgb(0xd55e00), // vermillion
+    rgb(0xcc79a7), // reddish purple
+    rgb(0xffffff),
+];
+const PROTANOPIA: [Color; KINDS] = [
+    rgb(0xe69f00), // orange
+    rgb(0x56b4e9), // sky blue
+    rgb(0xf0e442), // yellow
+    rgb(0x5e8cff), // blue
+    rgb(0xcc79a7), // reddish purple
+    rgb(0xffffff),
+];
+const TRITANOPIA: [Color; KINDS] = [
+    rgb(0xff4d4d), // red
+    rgb(0x2ed3d3), // teal
+    rgb(0xff9bd2), // pink
+    rgb(0x9e9e9e), // gray
+    rgb(0xc83c8c), // magenta
+    rgb(0xffffff),
+];
+const HIGH_CONTRAST: [Color; KINDS] = [
+    rgb(0xffff00),
+    rgb(0x00ffff),
+    rgb(0xff00ff),
+    rgb(0x00ff00),
+    rgb(0xff8000),
+    rgb(0xffffff),
+];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Palette {
+    // any color, as long as it stands out from the background
+    Random,
+    Deuteranopia,
+    Protanopia,
+    Tritanopia,
+    HighContrast,
+}
+
+impl Palette {
+    pub const ALL: [Palette; 5] = [
+        Palette::Random,
+        Palette::Deuteranopia,
+        Palette::Protanopia,
+        Palette::Tritanopia,
+        Palette::HighContrast,
+    ];
+
+    pub fn parse(name: &str) -> Option<Palette> {
+        let name = name.to_ascii_lowercase();
+        Self::ALL.into_iter().find(|palette| palette.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Palette::Random => "random",
+            Palette::Deuteranopia => "deuteranopia",
+            Palette::Protanopia => "protanopia",
+            Palette::Tritanopia => "tritanopia",
+            Palette::HighContrast => "high-contrast",
+        }
+    }
+
+    fn colors(self) -> Option<&'static [Color; KINDS]> {
+        match self {
+            Palette::Random => None,
+            Palette::Deuteranopia => Some(&DEUTERANOPIA),
+            Palette::Protanopia => Some(&PROTANOPIA),
+            Palette::Tritanopia => Some(&TRITANOPIA),
+            Palette::HighContrast => Some(&HIGH_CONTRAST),
+        }
+    }
+}
+
+// A symbol drawn on an enemy in the background color, one for each kind, so
+// that color is not the only way to tell them apart.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mark {
+    Dot,
+    Ring,
+    Bar,
+    Cross,
+    Triangle,
+    Square,
+}
+
+const MARKS: [Mark; KINDS] = [Mark::Dot, Mark::Ring, Mark::Bar, Mark::Cross, Mark::Triangle, Mark::Square];
+
+// How enemies are shown. Their random colors are drawn the same way whatever
+// this is, so replays and seeds give the same game with any palette.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct EnemyStyle {
+    pub palette: Palette,
+    pub marks: bool,
+}
+
+impl Default for EnemyStyle {
+    fn default() -> Self {
+        EnemyStyle {
+            palette: Palette::Random,
+            marks: false,
+        }
+    }
+}
+
+impl EnemyStyle {
+    // The color and mark of an enemy whose random color is `tone`.
+    pub fn look(&self, tone: Color) -> (Color, Option<Mark>) {
+        let kind = kind(tone);
+        let color = match self.palette.colors() {
+            Some(colors) => colors[kind],
+            None => tone,
+        };
+        (with_min_contrast(color), self.marks.then_some(MARKS[kind]))
+    }
+}
+
+fn kind(color: Color) -> usize {
+    let (r, g, b) = (color.r(), color.g(), color.b());
+    let max = r.max(g).max(b);
+    let range = max - r.min(g).min(b);
+    if range <= 0.0 {
+        return 0;
+    }
+    let sector = if max == r {
+        (g - b) / range
+    } else if max == g {
+        (b - r) / range + 2.0
+    } else {
+        (r - g) / range + 4.0
+    };
+    let hue = sector.rem_euclid(6.0) / 6.0;
+    ((hue * KINDS as f32) as usize).min(KINDS - 1)
+}
+
+// Relative luminance as defined by WCAG, from 0 for black to 1 for white.
+fn luminance(color: Color) -> f32 {
+    let linear = |c: f32| {
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * linear(color.r()) + 0.7152 * linear(color.g()) + 0.0722 * linear(color.b())
+}
+
+// Brightens a color towards white until it contrasts enough with black,
+// keeping its hue.
+fn with_min_contrast(color: Color) -> Color {
+    let min_luminance = MIN_CONTRAST * 0.05 - 0.05;
+    if luminance(color) >= min_luminance {
+        return color;
+    }
+    let lighten = |t: f32| {
+        let mix = |c: f32| c + (1.0 - c) * t;
+        Color::from_rgba(mix(color.r()), mix(color.g()), mix(color.b()), color.a())
+    };
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..12 {
+        let t = (low + high) / 2.0;
+        if luminance(lighten(t)) < min_luminance {
+            low = t;
+        } else {
+            high = t;
+        }
+    }
+    lighten(high)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contrast(color: Color) -> f32 {
+        (luminance(color) + 0.05) / 0.05
+    }
+
+    #[test]
+    fn dark_colors_are_brightened_to_the_minimum_contrast() {
+        for color in [Color::BLACK, rgb(0x200000), rgb(0x000040), rgb(0x102010)] {
+            let bright = with_min_contrast(color);
+            assert!(contrast(bright) >= MIN_CONTRAST, "{:?} only has {}", bright, contrast(bright));
+            assert!(contrast(bright) < MIN_CONTRAST + 0.05, "{:?} was brightened too far", bright);
+        }
+        // bright enough already
+        let orange = rgb(0xe69f00);
+        assert_eq!(with_min_contrast(orange), orange);
+    }
+
+    #[test]
+    fn every_palette_color_stands_out() {
+        for palette in Palette::ALL {
+            for color in palette.colors().into_iter().flatten() {
+                assert!(contrast(*color) >= MIN_CONTRAST, "{} has {:?}", palette.name(), color);
+            }
+        }
+    }
+
+    #[test]
+    fn look_picks_color_and_mark_by_hue() {
+        let red = Color::from_rgb(0.9, 0.1, 0.1);
+        let blue = Color::from_rgb(0.1, 0.2, 0.9);
+        let style = EnemyStyle {
+            palette: Palette::HighContrast,
+            marks: true,
+        };
+        assert_eq!(style.look(red), (HIGH_CONTRAST[kind(red)], Some(MARKS[kind(red)])));
+        assert_ne!(style.look(red), style.look(blue));
+        // similar hues look the same
+        assert_eq!(style.look(red), style.look(Color::from_rgb(0.6, 0.15, 0.1)));
+
+        let (color, mark) = EnemyStyle::default().look(red);
+        assert_eq!((color, mark), (red, None));
+    }
+
+    #[test]
+    fn parses_names_in_any_case() {
+        assert_eq!(Palette::parse("High-Contrast"), Some(Palette::HighContrast));
+        assert_eq!(Palette::parse("deuteranopia"), Some(Palette::Deuteranopia));
+        assert_eq!(Palette::parse("sepia"), None);
+    }
+}
@@ -1,86 +1,258 @@
 use std::f32::consts::TAU;
-use std::fmt;
-use std::fmt::Formatter;
-use speedy2d::dimen::Vec2;
+
 use speedy2d::color::Color;
-use speedy2d::{Graphics2D};
-use rand::random;
+use speedy2d::dimen::Vec2;
 
-#[derive(Clone)]
-pub struct Particle {
-    pub pos: Vec2,
-    pub vel: Vec2,
-    pub drag_friction: f32,
-    pub alpha_decay: f32,
-    pub radius: f32,
-    pub color: Color,
+use crate::canvas::Canvas;
+use crate::rng;
+
+// Particles are only for show, so they use the cosmetic generator rather than
+// the gameplay one: how many get spawned never changes the simulation.
+fn between(range: (f32, f32)) -> f32 {
+    range.0 + rng::cosmetic::<f32>() * (range.1 - range.0)
 }
 
-impl fmt::Debug for Particle {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        f.debug_struct("Particle")
-            .field("pos", &self.pos)
-            .field("vel", &self.vel)
-            .field("drag_friction", &self.drag_friction)
-            .field("alpha_decay", &self.alpha_decay)
-            .field("radius", &self.radius)
-            .field("color", &self.color)
-            .finish()
-    }
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EmitMode {
+    // this many particles at once
+    Burst(u32),
+    // this many particles per second for as long as the emitter runs
+    Continuous(f32),
 }
 
-impl Particle {
-    pub fn new(radius: f32, color: Color, pos: Vec2, vel: Vec2, drag_friction: f32, alpha_decay: f32) -> Self {
-        Particle {
-            pos,
-            vel,
-            drag_friction,
-            alpha_decay,
-            radius,
-            color,
+// A reusable description of an effect. Each particle gets a random direction
+// within `spread` radians around `direction`, and random speed, size and
+// lifetime from the ranges. Its color is the emitter's tint multiplied by the
+// gradient, so one explosion definition serves enemies of every color.
+#[derive(Clone, Debug)]
+pub struct Emitter {
+    pub mode: EmitMode,
+    pub direction: f32,
+    pub spread: f32,
+    pub speed: (f32, f32),
+    pub size: (f32, f32),
+    pub lifetime: (f32, f32), // seconds
+    pub end_size: f32, // fraction of the starting size at the end of life
+    pub drag: f32, // fraction of the velocity kept per second
+    pub gravity: Vec2,
+    pub gradient: Vec<(f32, Color)>, // (age from 0 to 1, color), sorted by age
+}
+
+impl Emitter {
+    // Sparks flying away in every direction, as when something is hit.
+    pub fn explosion(count: u32, speed: f32) -> Self {
+        Emitter {
+            mode: EmitMode::Burst(count),
+            direction: 0.0,
+            spread: TAU,
+            speed: (0.0, speed),
+            size: (3.0, 6.0),
+            lifetime: (0.5, 0.9),
+            end_size: 0.0,
+            drag: 0.55,
+            gravity: Vec2::ZERO,
+            gradient: vec![
+                (0.0, Color::WHITE),
+                (0.6, Color::from_rgba(1.0, 1.0, 1.0, 0.8)),
+                (1.0, Color::from_rgba(1.0, 0.6, 0.6, 0.0)),
+            ],
         }
     }
 
-    fn retain(&self) -> bool {
-        self.radius > 0.01
+    // A thin stream of embers drifting up while the super bang charges.
+    pub fn charge() -> Self {
+        Emitter {
+            mode: EmitMode::Continuous(60.0),
+            direction: -TAU / 4.0,
+            spread: TAU / 6.0,
+            speed: (20.0, 60.0),
+            size: (1.5, 3.0),
+            lifetime: (0.4, 0.8),
+            end_size: 0.5,
+            drag: 0.8,
+            gravity: Vec2::new(0.0, -40.0),
+            gradient: vec![
+                (0.0, Color::from_rgba(1.0, 1.0, 1.0, 0.0)),
+                (0.2, Color::WHITE),
+                (1.0, Color::from_rgba(1.0, 0.8, 0.2, 0.0)),
+            ],
+        }
     }
 
-    pub fn draw(&self, graphics: &mut Graphics2D) {
-        graphics.draw_circle((self.pos.x, self.pos.y), self.radius, self.color);
+    fn color_at(&self, t: f32) -> Color {
+        let Some(&(_, first)) = self.gradient.first() else {
+            return Color::WHITE;
+        };
+        let mut color = first;
+        for pair in self.gradient.windows(2) {
+            let ((from_t, from), (to_t, to)) = (pair[0], pair[1]);
+            if t >= from_t {
+                let f = if to_t > from_t { ((t - from_t) / (to_t - from_t)).min(1.0) } else { 1.0 };
+                let mix = |a: f32, b: f32| a + (b - a) * f;
+                color = Color::from_rgba(
+                    mix(from.r(), to.r()),
+                    mix(from.g(), to.g()),
+                    mix(from.b(), to.b()),
+                    mix(from.a(), to.a()),
+                );
+            }
+        }
+        color
     }
+}
 
+// Handle to an emitter registered with a `ParticlePool`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct EmitterId(usize);
 
-    pub fn update(&mut self, dt: f32) -> bool {
-        if self.alpha_decay < 1.0 {
-            let mut alpha = self.color.a() * self.alpha_decay;
-            if alpha < 0.01 {
-                alpha = 0.0;
-            }
-            self.color = Color::from_rgba(self.color.r(), self.color.g(), self.color.b(), alpha)
+// A continuous emitter placed in the world. Keeps the fraction of a particle
+// left over from the last frame so low rates still emit evenly.
+pub struct EmitterInstance {
+    pub emitter: EmitterId,
+    pub pos: Vec2,
+    pub tint: Color,
+    carry: f32,
+}
+
+impl EmitterInstance {
+    pub fn new(emitter: EmitterId, pos: Vec2, tint: Color) -> Self {
+        EmitterInstance {
+            emitter,
+            pos,
+            tint,
+            carry: 0.0,
         }
+    }
+}
 
-        self.radius -= 0.1;
+// All live particles, stored as one array per property so the update loop
+// walks memory in order. The capacity is fixed when the pool is created and
+// never grows: as the pool fills up, new bursts are thinned out, and once it
+// is full further particles are dropped.
+pub struct ParticlePool {
+    emitters: Vec<Emitter>,
+    capacity: usize,
+    pos: Vec<Vec2>,
+    vel: Vec<Vec2>,
+    age: Vec<f32>,
+    lifetime: Vec<f32>,
+    size: Vec<f32>,
+    tint: Vec<Color>,
+    emitter: Vec<EmitterId>,
+}
+
+// Bursts are spawned in full while the pool is less than this full, then
+// scaled down linearly to nothing as it reaches capacity.
+const THINNING_START: f32 = 0.5;
+
+impl ParticlePool {
+    pub fn new(capacity: usize) -> Self {
+        ParticlePool {
+            emitters: Vec::new(),
+            capacity,
+            pos: Vec::with_capacity(capacity),
+            vel: Vec::with_capacity(capacity),
+            age: Vec::with_capacity(capacity),
+            lifetime: Vec::with_capacity(capacity),
+            size: Vec::with_capacity(capacity),
+            tint: Vec::with_capacity(capacity),
+            emitter: Vec::with_capacity(capacity),
+        }
+    }
 
-        self.vel = self.vel * self.drag_friction;
-        self.pos = self.pos + self.vel * dt;
+    pub fn register(&mut self, emitter: Emitter) -> EmitterId {
+        self.emitters.push(emitter);
+        EmitterId(self.emitters.len() - 1)
+    }
 
-        self.retain()
+    // Swaps a registered definition, e.g. after the tuning changed.
+    pub fn replace(&mut self, id: EmitterId, emitter: Emitter) {
+        self.emitters[id.0] = emitter;
     }
 
-    pub fn spawn_particles(particles: &mut Vec<Particle>, num_particles: u32, speed: f32, color: Color, pos: Vec2) {
-        for _ in 0..num_particles {
-            let angle = random::<f32>() * TAU;
-            let speed = random::<f32>() * speed;
+    pub fn len(&self) -> usize {
+        self.pos.len()
+    }
 
-            let vel = Vec2::new(angle.cos() * speed, angle.sin() * speed);
+    // Fires a burst emitter once.
+    pub fn burst(&mut self, id: EmitterId, pos: Vec2, tint: Color) {
+        let count = match self.emitters[id.0].mode {
+            EmitMode::Burst(count) => count,
+            EmitMode::Continuous(rate) => rate as u32,
+        };
+        let fill = self.len() as f32 / self.capacity.max(1) as f32;
+        let share = ((1.0 - fill) / (1.0 - THINNING_START)).min(1.0);
+        let count = (count as f32 * share).round() as usize;
+        self.spawn(id, count, pos, tint);
+    }
 
-            let particle = Particle::new(5.0, color,
-                                         pos,
-                                         vel,
-                                         0.99,
-                                         1.0);
+    // Runs a continuous emitter for one frame.
+    pub fn emit(&mut self, instance: &mut EmitterInstance, dt: f32) {
+        let rate = match self.emitters[instance.emitter.0].mode {
+            EmitMode::Continuous(rate) => rate,
+            EmitMode::Burst(count) => count as f32,
+        };
+        instance.carry += rate * dt;
+        let count = instance.carry.floor();
+        instance.carry -= count;
+        self.spawn(instance.emitter, count as usize, instance.pos, instance.tint);
+    }
 
-            particles.push(particle);
+    fn spawn(&mut self, id: EmitterId, count: usize, pos: Vec2, tint: Color) {
+        let count = count.min(self.capacity - self.len());
+        let emitter = &self.emitters[id.0];
+        for _ in 0..count {
+            let angle = emitter.direction + (rng::cosmetic::<f32>() - 0.5) * emitter.spread;
+            let (sin, cos) = angle.sin_cos();
+            self.pos.push(pos);
+            self.vel.push(Vec2::new(cos, sin) * between(emitter.speed));
+            self.age.push(0.0);
+            self.lifetime.push(between(emitter.lifetime));
+            self.size.push(between(emitter.size));
+            self.tint.push(tint);
+            self.emitter.push(id);
         }
     }
-}
\ No newline at end of file
+
+    fn swap_remove(&mut self, index: usize) {
+        self.pos.swap_remove(index);
+        self.vel.swap_remove(index);
+        self.age.swap_remove(index);
+        self.lifetime.swap_remove(index);
+        self.size.swap_remove(index);
+        self.tint.swap_remove(index);
+        self.emitter.swap_remove(index);
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        let mut index = 0;
+        while index < self.len() {
+            self.age[index] += dt;
+            if self.age[index] >= self.lifetime[index] {
+                self.swap_remove(index);
+                continue;
+            }
+            let emitter = &self.emitters[self.emitter[index].0];
+            self.vel[index] = (self.vel[index] + emitter.gravity * dt) * emitter.drag.powf(dt);
+            self.pos[index] += self.vel[index] * dt;
+            index += 1;
+        }
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas) {
+        for index in 0..self.len() {
+            let emitter = &self.emitters[self.emitter[index].0];
+            let t = self.age[index] / self.lifetime[index];
+            let radius = self.size[index] * (1.0 + (emitter.end_size - 1.0) * t);
+            let shade = emitter.color_at(t);
+            let tint = self.tint[index];
+            let color = Color::from_rgba(
+                tint.r() * shade.r(),
+                tint.g() * shade.g(),
+                tint.b() * shade.b(),
+                tint.a() * shade.a(),
+            );
+            canvas.draw_circle(self.pos[index], radius, color);
+        }
+    }
+}
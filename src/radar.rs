@@ -0,0 +1,104 @@
+use speedy2d::color::Color;
+use speedy2d::dimen::Vec2;
+use speedy2d::shape::Rectangle;
+
+use crate::camera::Camera;
+use crate::canvas::Canvas;
+use crate::render::Scene;
+
+// Everything here is drawn in HUD coordinates.
+
+const EDGE_MARGIN: f32 = 20.0; // between an arrow's tip and the edge of the screen
+const MAX_ARROW: f32 = 24.0; // for enemies just off screen
+const MIN_ARROW: f32 = 10.0;
+const FAR: f32 = 800.0; // world units off screen at which arrows stop shrinking
+
+const MINIMAP_SIZE: f32 = 200.0; // the longer side
+const MINIMAP_MARGIN: f32 = 20.0;
+const MINIMAP_BACKGROUND: Color = Color::from_rgba(0.0, 0.0, 0.0, 0.6);
+const MINIMAP_FRAME: Color = Color::from_rgba(1.0, 1.0, 1.0, 0.4);
+
+// Where the ray from `from` towards `to` leaves the box, or None if `to` is
+// inside it. `from` has to be inside.
+fn exit_point(from: Vec2, to: Vec2, min: Vec2, max: Vec2) -> Option<Vec2> {
+    if (min.x..=max.x).contains(&to.x) && (min.y..=max.y).contains(&to.y) {
+        return None;
+    }
+    let direction = to - from;
+    let axis = |from: f32, direction: f32, min: f32, max: f32| {
+        if direction > 0.0 {
+            (max - from) / direction
+        } else if direction < 0.0 {
+            (min - from) / direction
+        } else {
+            f32::INFINITY
+        }
+    };
+    let t = axis(from.x, direction.x, min.x, max.x).min(axis(from.y, direction.y, min.y, max.y));
+    Some(from + direction * t.clamp(0.0, 1.0))
+}
+
+// An arrow at the edge of the screen for every enemy that is entirely off it,
+// pointing along the line from the player. Closer enemies get bigger arrows.
+pub fn draw_indicators(canvas: &mut Canvas, camera: &Camera, hud_size: Vec2, scene: &Scene) {
+    let min = Vec2::new(EDGE_MARGIN, EDGE_MARGIN);
+    let max = hud_size - min;
+    let player = camera.to_hud(scene.player.pos);
+    let from = Vec2::new(player.x.clamp(min.x, max.x), player.y.clamp(min.y, max.y));
+
+    for enemy in scene.enemies {
+        let pos = camera.to_hud(enemy.pos);
+        let radius = enemy.radius * camera.zoom;
+        let outside = Vec2::new(
+            (-radius - pos.x).max(pos.x - hud_size.x - radius).max(0.0),
+            (-radius - pos.y).max(pos.y - hud_size.y - radius).max(0.0),
+        );
+        if outside == Vec2::ZERO {
+            continue;
+        }
+        let Some(tip) = exit_point(from, pos, min, max) else {
+            continue;
+        };
+        let Some(direction) = (pos - from).normalize() else {
+            continue;
+        };
+
+        let distance = outside.magnitude() / camera.zoom;
+        let size = MAX_ARROW - (MAX_ARROW - MIN_ARROW) * (distance / FAR).min(1.0);
+        let back = tip - direction * size;
+        let side = direction.rotate_90_degrees_clockwise() * (size * 0.6);
+        let thickness = size * 0.3;
+        canvas.draw_line(tip, back + side, thickness, enemy.color);
+        canvas.draw_line(tip, back - side, thickness, enemy.color);
+        // round off the point where the two lines overlap
+        canvas.draw_circle(tip, thickness / 2.0, enemy.color);
+    }
+}
+
+// The whole arena in the bottom right corner, with the part on screen framed.
+pub fn draw_minimap(canvas: &mut Canvas, camera: &Camera, hud_size: Vec2, scene: &Scene) {
+    let arena = scene.arena_size;
+    let scale = MINIMAP_SIZE / arena.x.max(arena.y);
+    let size = arena * scale;
+    let origin = hud_size - size - Vec2::new(MINIMAP_MARGIN, MINIMAP_MARGIN);
+    let to_map = |pos: Vec2| origin + pos * scale;
+
+    canvas.draw_rectangle(&Rectangle::new(origin, origin + size), MINIMAP_BACKGROUND);
+    let (top_left, bottom_right) = camera.visible_rect();
+    let top_left = to_map(Vec2::new(top_left.x.max(0.0), top_left.y.max(0.0)));
+    let bottom_right = to_map(Vec2::new(bottom_right.x.min(arena.x), bottom_right.y.min(arena.y)));
+    let corners = [
+        top_left,
+        Vec2::new(bottom_right.x, top_left.y),
+        bottom_right,
+        Vec2::new(top_left.x, bottom_right.y),
+    ];
+    for (index, corner) in corners.iter().enumerate() {
+        canvas.draw_line(*corner, corners[(index + 1) % corners.len()], 1.0, MINIMAP_FRAME);
+    }
+
+    for enemy in scene.enemies {
+        canvas.draw_circle(to_map(enemy.pos), (enemy.radius * scale).max(1.5), enemy.color);
+    }
+    canvas.draw_circle(to_map(scene.player.pos), 3.0, Color::WHITE);
+}
@@ -0,0 +1,126 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use speedy2d::color::Color;
+use speedy2d::dimen::Vec2;
+use speedy2d::shape::Rectangle;
+
+// A small software renderer with the shapes the game draws, so frames can be
+// rendered without a window or a GPU. Edges are antialiased by pixel coverage
+// and colors blend by alpha like they do in speedy2d. The image is opaque.
+pub struct Raster {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<[f32; 3]>,
+    clip: (i32, i32, i32, i32), // left, top, right, bottom; right and bottom exclusive
+}
+
+impl Raster {
+    pub fn new(width: u32, height: u32) -> Self {
+        Raster {
+            width,
+            height,
+            pixels: vec![[0.0; 3]; (width * height) as usize],
+            clip: (0, 0, width as i32, height as i32),
+        }
+    }
+
+    pub fn set_clip(&mut self, clip: Option<Rectangle<i32>>) {
+        let (width, height) = (self.width as i32, self.height as i32);
+        self.clip = match clip {
+            Some(rect) => (
+                rect.top_left().x.clamp(0, width),
+                rect.top_left().y.clamp(0, height),
+                rect.bottom_right().x.clamp(0, width),
+                rect.bottom_right().y.clamp(0, height),
+            ),
+            None => (0, 0, width, height),
+        };
+    }
+
+    // Calls `coverage` with the center of every pixel in the box that is
+    // inside the clip rectangle, and blends `color` by what it returns.
+    fn fill(&mut self, min: Vec2, max: Vec2, color: Color, coverage: impl Fn(Vec2) -> f32) {
+        let left = (min.x.floor() as i32).max(self.clip.0);
+        let top = (min.y.floor() as i32).max(self.clip.1);
+        let right = (max.x.ceil() as i32).min(self.clip.2);
+        let bottom = (max.y.ceil() as i32).min(self.clip.3);
+        for y in top..bottom {
+            for x in left..right {
+                let alpha = coverage(Vec2::new(x as f32 + 0.5, y as f32 + 0.5)).clamp(0.0, 1.0) * color.a();
+                if alpha <= 0.0 {
+                    continue;
+                }
+                let pixel = &mut self.pixels[(y as u32 * self.width + x as u32) as usize];
+                for (channel, value) in pixel.iter_mut().zip([color.r(), color.g(), color.b()]) {
+                    *channel += (value - *channel) * alpha;
+                }
+            }
+        }
+    }
+
+    pub fn draw_circle(&mut self, center: Vec2, radius: f32, color: Color) {
+        let extent = Vec2::new(radius + 1.0, radius + 1.0);
+        self.fill(center - extent, center + extent, color, |pos| {
+            radius - (pos - center).magnitude() + 0.5
+        });
+    }
+
+    // A bright spot that fades out towards `radius`.
+    pub fn draw_glow(&mut self, center: Vec2, radius: f32, color: Color) {
+        let extent = Vec2::new(radius, radius);
+        self.fill(center - extent, center + extent, color, |pos| {
+            (1.0 - (pos - center).magnitude() / radius).max(0.0).powi(2)
+        });
+    }
+
+    // The same rectangle along the line as `Graphics2D::draw_line`.
+    pub fn draw_line(&mut self, start: Vec2, end: Vec2, thickness: f32, color: Color) {
+        let Some(direction) = (end - start).normalize() else {
+            return;
+        };
+        let length = (end - start).magnitude();
+        let half = thickness / 2.0;
+        let extent = Vec2::new(half + 1.0, half + 1.0);
+        let min = Vec2::new(start.x.min(end.x), start.y.min(end.y)) - extent;
+        let max = Vec2::new(start.x.max(end.x), start.y.max(end.y)) + extent;
+        self.fill(min, max, color, |pos| {
+            let offset = pos - start;
+            let along = offset.x * direction.x + offset.y * direction.y;
+            let across = (offset.x * direction.y - offset.y * direction.x).abs();
+            let ends = (along.min(length - along) + 0.5).clamp(0.0, 1.0);
+            (half - across + 0.5).clamp(0.0, 1.0) * ends
+        });
+    }
+
+    pub fn draw_rectangle(&mut self, rect: &Rectangle, color: Color) {
+        let (min, max) = (*rect.top_left(), *rect.bottom_right());
+        self.fill(min, max, color, |pos| {
+            let x = (pos.x + 0.5).min(max.x) - (pos.x - 0.5).max(min.x);
+            let y = (pos.y + 0.5).min(max.y) - (pos.y - 0.5).max(min.y);
+            x.max(0.0) * y.max(0.0)
+        });
+    }
+
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|pixel| pixel.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8))
+            .collect()
+    }
+
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|pixel| {
+                let [r, g, b] = pixel.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+                [r, g, b, 255]
+            })
+            .collect()
+    }
+
+    pub fn save_png(&self, path: &Path) -> Result<()> {
+        image::save_buffer(path, &self.to_rgb8(), self.width, self.height, image::ColorType::Rgb8)
+            .with_context(|| format!("writing {}", path.display()))
+    }
+}
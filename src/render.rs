@@ -0,0 +1,173 @@
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use speedy2d::color::Color;
+use speedy2d::dimen::{UVec2, Vec2};
+
+use crate::bullet::Bullet;
+use crate::canvas::{Canvas, View};
+use crate::enemy::Enemy;
+use crate::game::Game;
+use crate::game_entity::GameEntity;
+use crate::particle::ParticlePool;
+use crate::player::Player;
+use crate::raster::Raster;
+use crate::trail::EffectsStyle;
+
+const GLOW_SCALE: f32 = 4.0; // bullet glow radius, relative to the bullet
+const MIN_GLOW_RADIUS: f32 = 12.0;
+
+// The things in the arena that get drawn, borrowed from the running game or
+// from a recorded snapshot.
+pub struct Scene<'a> {
+    pub arena_size: Vec2,
+    pub player: &'a Player,
+    pub enemies: &'a [Enemy],
+    pub bullets: &'a [Bullet],
+    pub particles: &'a ParticlePool,
+}
+
+impl<'a> From<&'a Game> for Scene<'a> {
+    fn from(game: &'a Game) -> Self {
+        Scene {
+            arena_size: game.arena_size,
+            player: &game.player,
+            enemies: &game.enemies,
+            bullets: &game.bullets,
+            particles: &game.particles,
+        }
+    }
+}
+
+// Draws everything in the arena, in world coordinates. The window and the
+// offscreen renderer share this, so screenshots, rendered frames and clips
+// look like the game.
+pub fn draw_world(canvas: &mut Canvas, scene: &Scene, effects: EffectsStyle) {
+    if effects == EffectsStyle::Trails {
+        draw_effects(canvas, scene);
+    }
+    draw_entities(canvas, scene);
+}
+
+// The player, enemies, bullets and particles, without trails or glows.
+pub fn draw_entities(canvas: &mut Canvas, scene: &Scene) {
+    scene.player.draw(canvas);
+
+    for enemy in scene.enemies {
+        enemy.draw(canvas);
+    }
+
+    for bullet in scene.bullets {
+        bullet.draw(canvas);
+    }
+
+    scene.particles.draw(canvas);
+}
+
+// Trails behind enemies and bullets, and a glow around every bullet.
+pub fn draw_effects(canvas: &mut Canvas, scene: &Scene) {
+    for enemy in scene.enemies {
+        enemy.trail.draw(canvas, enemy.radius * 2.0, enemy.color);
+    }
+    for bullet in scene.bullets {
+        bullet.trail.draw(canvas, bullet.radius * 2.0, bullet.color);
+    }
+    for bullet in scene.bullets {
+        let radius = (bullet.radius * GLOW_SCALE).max(MIN_GLOW_RADIUS);
+        let color = Color::from_rgba(bullet.color.r(), bullet.color.g(), bullet.color.b(), bullet.color.a() * 0.5);
+        canvas.draw_glow(bullet.pos, radius, color);
+    }
+}
+
+// Renders the whole arena, letterboxed into an image of the given size,
+// without a window.
+pub fn render_frame(scene: &Scene, effects: EffectsStyle, size: UVec2) -> Raster {
+    render_with(scene.arena_size, size, |canvas| draw_world(canvas, scene, effects))
+}
+
+// Like `render_frame`, with the arena drawn by `draw` in world coordinates.
+pub fn render_with(arena_size: Vec2, size: UVec2, draw: impl FnOnce(&mut Canvas)) -> Raster {
+    let mut raster = Raster::new(size.x, size.y);
+    let mut canvas = Canvas::offscreen(&mut raster, View::letterbox(size, arena_size));
+    canvas.clip_to(arena_size);
+    draw(&mut canvas);
+    drop(canvas);
+    raster
+}
+
+// A name in the working directory that sorts by time, like
+// `colorbang-1760000000123.png`.
+pub fn timestamped_path(extension: &str) -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis())
+        .unwrap_or_default();
+    PathBuf::from(format!("colorbang-{}.{}", millis, extension))
+}
+
+// Encodes a captured window on another thread so the game doesn't stutter.
+pub fn save_screenshot(rgb: Vec<u8>, size: UVec2, path: &Path) {
+    let path = path.to_path_buf();
+    thread::spawn(move || {
+        match image::save_buffer(&path, &rgb, size.x, size.y, image::ColorType::Rgb8) {
+            Ok(()) => println!("Saved screenshot to {}", path.display()),
+            Err(err) => eprintln!("warning: could not save {}: {}", path.display(), err),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Difficulty;
+    use crate::config::GameConfig;
+    use crate::input::Action;
+    use crate::rng;
+    use crate::sound::SoundPlayer;
+
+    const GOLDEN: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/seed7-level8-tick240.png");
+    // Float math and blending can round differently on other platforms and
+    // compilers, so pixels may be off by a little and a few edge pixels by more.
+    const CHANNEL_TOLERANCE: u8 = 8;
+    const MAX_DIFFERING: usize = 50;
+
+    // A fixed game, firing all around so there are bullets, explosions and
+    // trails in the frame, against an image checked in next to the code.
+    // Run with UPDATE_GOLDEN=1 to write the image again after an intended
+    // change to the drawing.
+    #[test]
+    fn frame_matches_golden_image() {
+        rng::seed_cosmetic(7);
+        let arena_size = Vec2::new(crate::ARENA_WIDTH, crate::ARENA_HEIGHT);
+        let config = GameConfig::preset(Difficulty::Normal);
+        let mut game = Game::new(7, 8, Difficulty::Normal, config, arena_size, SoundPlayer::null());
+        game.handle_action(Action::FireStart);
+        for tick in 0..240 {
+            let angle = tick as f32 * 0.05;
+            game.handle_action(Action::Aim(game.player.pos + Vec2::new(angle.cos(), angle.sin()) * 100.0));
+            game.tick(1.0 / 60.0);
+        }
+
+        let size = UVec2::new(480, 360);
+        let raster = render_frame(&Scene::from(&game), EffectsStyle::Trails, size);
+        let path = Path::new(GOLDEN);
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            raster.save_png(path).unwrap();
+        }
+
+        let golden = image::open(path).expect("missing golden image, run with UPDATE_GOLDEN=1").into_rgb8();
+        assert_eq!(golden.dimensions(), (size.x, size.y));
+        let rendered = raster.to_rgb8();
+        let differing = golden
+            .as_raw()
+            .chunks(3)
+            .zip(rendered.chunks(3))
+            .filter(|(golden, rendered)| {
+                golden.iter().zip(rendered.iter()).any(|(a, b)| a.abs_diff(*b) > CHANNEL_TOLERANCE)
+            })
+            .count();
+        assert!(differing <= MAX_DIFFERING, "{} pixels differ from {}", differing, GOLDEN);
+    }
+}
@@ -0,0 +1,219 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use speedy2d::dimen::Vec2;
+
+use crate::cli::Difficulty;
+use crate::config::GameConfig;
+use crate::game::MAX_LEVEL;
+use crate::input::Action;
+
+const MAGIC: &str = "colorbang-replay 2";
+// Earlier replays spent random numbers on particles, which no longer happens,
+// so their enemies would spawn elsewhere.
+const OLD_MAGIC: &str = "colorbang-replay 1";
+
+// Replays store the seed and starting conditions followed by every action and
+// the length of every frame, so playing one back runs the exact same
+// simulation steps. Gameplay settings that differ from the difficulty preset
+// are stored as `config` lines:
+//
+//     colorbang-replay 2
+//     seed 42
+//     level 1
+//     difficulty normal
+//     arena 1280 960
+//     config bullet_speed 250
+//     aim 640 300
+//     fire_start
+//     tick 0.016667
+#[derive(Clone, Copy)]
+pub struct ReplayHeader {
+    pub seed: u64,
+    pub level: u32,
+    pub difficulty: Difficulty,
+    pub arena_size: Vec2,
+    pub config: GameConfig,
+}
+
+pub struct Recorder {
+    writer: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, header: ReplayHeader) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        writeln!(writer, "{}", MAGIC)?;
+        writeln!(writer, "seed {}", header.seed)?;
+        writeln!(writer, "level {}", header.level)?;
+        writeln!(writer, "difficulty {}", header.difficulty.name())?;
+        writeln!(writer, "arena {} {}", header.arena_size.x, header.arena_size.y)?;
+        for (key, value) in header.config.changes_from(&GameConfig::preset(header.difficulty)) {
+            writeln!(writer, "config {} {}", key, value)?;
+        }
+        Ok(Recorder { writer })
+    }
+
+    pub fn action(&mut self, action: Action) {
+        let _ = writeln!(self.writer, "{}", action.to_text());
+    }
+
+    pub fn tick(&mut self, dt: f32) {
+        let _ = writeln!(self.writer, "tick {}", dt);
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+enum Step {
+    Action(Action),
+    Tick(f32),
+}
+
+pub struct Replay {
+    pub header: ReplayHeader,
+    steps: Vec<Step>,
+    next: usize,
+}
+
+fn header_value<'a>(line: Option<(usize, &'a str)>, key: &str) -> Result<&'a str> {
+    let (index, line) = line.ok_or_else(|| anyhow!("missing `{}` line", key))?;
+    match line.split_once(' ') {
+        Some((name, value)) if name == key => Ok(value.trim()),
+        _ => bail!("line {}: expected `{} <value>`", index + 1, key),
+    }
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("parsing replay {}", path.display()))
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+
+        match lines.next().map(|(_, line)| line.trim()) {
+            Some(MAGIC) => {}
+            Some(OLD_MAGIC) => bail!("this replay was recorded by an older version of the game and cannot be played back"),
+            _ => bail!("not a Color Bang! replay file"),
+        }
+
+        let seed = header_value(lines.next(), "seed")?;
+        let level = header_value(lines.next(), "level")?;
+        let difficulty = header_value(lines.next(), "difficulty")?;
+        let arena = header_value(lines.next(), "arena")?;
+        let arena_size = match arena.split_once(' ') {
+            Some((width, height)) => width.trim().parse().ok().zip(height.trim().parse().ok()),
+            None => None,
+        };
+        let (width, height) = arena_size.ok_or_else(|| anyhow!("invalid arena size `{}`", arena))?;
+        let difficulty = Difficulty::parse(difficulty).ok_or_else(|| anyhow!("unknown difficulty `{}`", difficulty))?;
+        let mut header = ReplayHeader {
+            seed: seed.parse().map_err(|_| anyhow!("invalid seed `{}`", seed))?,
+            level: level
+                .parse()
+                .ok()
+                .filter(|level| (1..=MAX_LEVEL).contains(level))
+                .ok_or_else(|| anyhow!("invalid level `{}`", level))?,
+            difficulty,
+            arena_size: Vec2::new(width, height),
+            config: GameConfig::preset(difficulty),
+        };
+
+        let mut steps = Vec::new();
+        for (index, line) in lines {
+            let line = line.trim();
+            if let (Some(setting), true) = (line.strip_prefix("config "), steps.is_empty()) {
+                let (key, value) = setting.split_once(' ').unwrap_or((setting, ""));
+                header
+                    .config
+                    .set(key, value.trim())
+                    .with_context(|| format!("line {}", index + 1))?;
+            } else if let Some(dt) = line.strip_prefix("tick ") {
+                let dt = dt
+                    .parse()
+                    .map_err(|_| anyhow!("line {}: invalid frame time `{}`", index + 1, dt))?;
+                steps.push(Step::Tick(dt));
+            } else {
+                let action = Action::from_text(line)
+                    .ok_or_else(|| anyhow!("line {}: unknown action `{}`", index + 1, line))?;
+                steps.push(Step::Action(action));
+            }
+        }
+
+        Ok(Replay { header, steps, next: 0 })
+    }
+
+    // Returns the actions recorded before the next frame together with that
+    // frame's length, or `None` once the replay is over.
+    pub fn next_frame(&mut self, actions: &mut Vec<Action>) -> Option<f32> {
+        while let Some(step) = self.steps.get(self.next) {
+            self.next += 1;
+            match step {
+                Step::Action(action) => actions.push(*action),
+                Step::Tick(dt) => return Some(*dt),
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPLAY: &str = "colorbang-replay 2
+seed 42
+level 3
+difficulty hard
+arena 1280 960
+config bullet_speed 250
+aim 640 300
+fire_start
+tick 0.016667
+
+tick 0.02
+fire_stop
+";
+
+    #[test]
+    fn parses_header_and_frames() {
+        let mut replay = Replay::parse(REPLAY).unwrap();
+        assert_eq!(replay.header.seed, 42);
+        assert_eq!(replay.header.level, 3);
+        assert_eq!(replay.header.difficulty, Difficulty::Hard);
+        assert_eq!(replay.header.arena_size, Vec2::new(1280.0, 960.0));
+        let mut config = GameConfig::preset(Difficulty::Hard);
+        config.set("bullet_speed", "250").unwrap();
+        assert!(replay.header.config == config);
+
+        let mut actions = Vec::new();
+        assert_eq!(replay.next_frame(&mut actions), Some(0.016667));
+        assert_eq!(actions, [Action::Aim(Vec2::new(640.0, 300.0)), Action::FireStart]);
+        actions.clear();
+        assert_eq!(replay.next_frame(&mut actions), Some(0.02));
+        assert!(actions.is_empty());
+        // actions after the last frame are never played
+        assert_eq!(replay.next_frame(&mut actions), None);
+    }
+
+    #[test]
+    fn rejects_broken_replays() {
+        let error = |text: &str| format!("{:#}", Replay::parse(text).err().unwrap());
+        assert!(error("colorbang-replay 1\n").contains("older version"));
+        assert!(error("something else\n").contains("not a Color Bang! replay"));
+        assert!(error(&REPLAY.replace("level 3", "wave 3")).contains("expected `level <value>`"));
+        assert!(error(&REPLAY.replace("arena 1280 960", "arena 1280")).contains("invalid arena size"));
+        assert!(error(&REPLAY.replace("level 3", "level 4000000000")).contains("invalid level"));
+        assert!(error(&REPLAY.replace("fire_start", "jump")).contains("line 8: unknown action `jump`"));
+        assert!(error(&REPLAY.replace("tick 0.02", "tick soon")).contains("invalid frame time"));
+    }
+}
@@ -0,0 +1,38 @@
+use std::cell::RefCell;
+
+use rand::distr::{Distribution, StandardUniform};
+use rand::rngs::StdRng;
+use rand::{RngExt, SeedableRng};
+
+// Gameplay randomness goes through this seedable generator so a given seed
+// (and replay) always produces the same world. Cosmetic randomness such as
+// sound variation keeps using `rand::random` and does not disturb it.
+//
+// Particles are cosmetic too, but they show up in rendered frames, so they
+// get a generator of their own that can be seeded for reproducible images.
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::seed_from_u64(rand::random()));
+    static COSMETIC: RefCell<StdRng> = RefCell::new(StdRng::seed_from_u64(rand::random()));
+}
+
+pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+pub fn random<T>() -> T
+where
+    StandardUniform: Distribution<T>,
+{
+    RNG.with(|rng| rng.borrow_mut().random())
+}
+
+pub fn seed_cosmetic(seed: u64) {
+    COSMETIC.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+pub fn cosmetic<T>() -> T
+where
+    StandardUniform: Distribution<T>,
+{
+    COSMETIC.with(|rng| rng.borrow_mut().random())
+}
@@ -0,0 +1,98 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::cli::Difficulty;
+
+// Kept next to the config file.
+pub const FILE_NAME: &str = "colorbang-scores.txt";
+pub const MAX_ENTRIES: usize = 10;
+pub const MAX_NAME_LENGTH: usize = 12;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct HighScore {
+    pub score: u64,
+    pub wave: u32,
+    pub difficulty: Difficulty,
+    pub name: String,
+}
+
+// The best scores, highest first, stored one per line as
+// `score wave difficulty name`.
+pub struct HighScores {
+    pub entries: Vec<HighScore>,
+    path: PathBuf,
+}
+
+fn parse_line(line: &str) -> Option<HighScore> {
+    let mut parts = line.splitn(4, ' ');
+    Some(HighScore {
+        score: parts.next()?.parse().ok()?,
+        wave: parts.next()?.parse().ok()?,
+        difficulty: Difficulty::parse(parts.next()?)?,
+        name: parts.next()?.trim().to_string(),
+    })
+}
+
+impl HighScores {
+    pub fn path_for(config_path: &Path) -> PathBuf {
+        config_path.with_file_name(FILE_NAME)
+    }
+
+    // A missing file is an empty table; a broken one is reported and treated
+    // the same, rather than keeping the game from starting.
+    pub fn load(path: PathBuf) -> Self {
+        let entries = match Self::read(&path) {
+            Ok(entries) => entries,
+            Err(err) => {
+                eprintln!("warning: could not load high scores: {:#}", err);
+                Vec::new()
+            }
+        };
+        HighScores { entries, path }
+    }
+
+    fn read(path: &Path) -> Result<Vec<HighScore>> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err).with_context(|| format!("reading {}", path.display())),
+        };
+        let mut entries = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                parse_line(line).ok_or_else(|| anyhow!("{} line {}: invalid entry", path.display(), index + 1))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.score));
+        entries.truncate(MAX_ENTRIES);
+        Ok(entries)
+    }
+
+    pub fn qualifies(&self, score: u64) -> bool {
+        score > 0 && (self.entries.len() < MAX_ENTRIES || self.entries.iter().any(|entry| score > entry.score))
+    }
+
+    // Adds the score and saves the table.
+    pub fn add(&mut self, entry: HighScore) {
+        let rank = self.entries.iter().position(|old| entry.score > old.score).unwrap_or(self.entries.len());
+        self.entries.insert(rank, entry);
+        self.entries.truncate(MAX_ENTRIES);
+        if let Err(err) = self.save() {
+            eprintln!("warning: could not save high scores: {:#}", err);
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        let text: String = self
+            .entries
+            .iter()
+            .map(|entry| format!("{} {} {} {}\n", entry.score, entry.wave, entry.difficulty.name(), entry.name))
+            .collect();
+        fs::write(&self.path, text).with_context(|| format!("writing {}", self.path.display()))
+    }
+}
@@ -0,0 +1,87 @@
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::cli::Options;
+use crate::ini;
+use crate::palette::{EnemyStyle, Palette};
+use crate::sound::AudioSettings;
+use crate::trail::EffectsStyle;
+use crate::ui::Panel;
+
+const VOLUME_STEP: f32 = 0.1;
+const EFFECTS: [EffectsStyle; 2] = [EffectsStyle::Trails, EffectsStyle::Smear];
+
+// The preferences on the settings screen. Changes take effect right away and
+// are written to the config file when the screen is left: volumes to the
+// `[audio]` section, the rest as the matching command-line options.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Settings {
+    pub audio: AudioSettings,
+    pub camera_follow: bool,
+    pub screen_shake: bool,
+    pub minimap: bool,
+    pub enemy_style: EnemyStyle,
+    pub batching: bool,
+    pub effects: EffectsStyle,
+}
+
+impl Settings {
+    pub fn from_options(options: &Options) -> Self {
+        Settings {
+            audio: options.audio,
+            camera_follow: options.camera_follow,
+            screen_shake: options.screen_shake,
+            minimap: options.minimap,
+            enemy_style: options.enemy_style,
+            batching: options.batching,
+            effects: options.effects,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let audio: Vec<(&str, String)> = AudioSettings::KEYS
+            .iter()
+            .map(|key| (*key, format!("{:.1}", self.audio.get(key).unwrap_or(1.0))))
+            .collect();
+        ini::update(path, "audio", &audio)?;
+        ini::update(
+            path,
+            "",
+            &[
+                ("no-camera-follow", (!self.camera_follow).to_string()),
+                ("no-screen-shake", (!self.screen_shake).to_string()),
+                ("minimap", self.minimap.to_string()),
+                ("palette", self.enemy_style.palette.name().to_string()),
+                ("enemy-marks", self.enemy_style.marks.to_string()),
+                ("no-batching", (!self.batching).to_string()),
+                ("effects", self.effects.name().to_string()),
+            ],
+        )
+    }
+
+    // Returns whether anything changed.
+    pub fn edit(&mut self, panel: &mut Panel) -> bool {
+        let old = *self;
+        for (key, label) in AudioSettings::KEYS.iter().zip(["Master volume", "Effects", "Interface", "Music"]) {
+            if let Some(volume) = self.audio.get_mut(key) {
+                panel.slider(label, volume, 0.0, 1.0, VOLUME_STEP);
+            }
+        }
+        panel.space();
+        panel.toggle("Camera follows player", &mut self.camera_follow);
+        panel.toggle("Screen shake", &mut self.screen_shake);
+        panel.toggle("Minimap", &mut self.minimap);
+        let mut palette = Palette::ALL.iter().position(|palette| *palette == self.enemy_style.palette).unwrap_or(0);
+        if panel.list("Enemy colors", &Palette::ALL.map(Palette::name), &mut palette) {
+            self.enemy_style.palette = Palette::ALL[palette];
+        }
+        panel.toggle("Enemy marks", &mut self.enemy_style.marks);
+        let mut style = EFFECTS.iter().position(|style| *style == self.effects).unwrap_or(0);
+        if panel.list("Effects style", &EFFECTS.map(EffectsStyle::name), &mut style) {
+            self.effects = EFFECTS[style];
+        }
+        panel.toggle("Batched drawing", &mut self.batching);
+        *self != old
+    }
+}
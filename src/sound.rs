@@ -1,82 +1,454 @@
-use kira::{
-    sound::static_sound::StaticSoundData, AudioManager, AudioManagerSettings, DefaultBackend,
-};
-use std::io::Cursor;
+use kira::sound::static_sound::StaticSoundHandle;
+use kira::sound::PlaybackState;
+use kira::track::{TrackBuilder, TrackHandle};
+use kira::{AudioManager, AudioManagerSettings, Decibels, DefaultBackend, Panning, Tween};
+use speedy2d::dimen::Vec2;
 
-use anyhow::{ Result};
-use std::collections::HashMap;
-use std::fs;
-use std::path::PathBuf;
+use anyhow::{Context, Result};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
 
+use crate::assets::Assets;
+use crate::music::{self, MusicPlayer};
+use crate::sound_bank::SoundBank;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum SoundType {
     Fire,
     Explode,
     MultiFire,
     Wave,
     Load,
+    PlayerHit,
+    Pickup,
 }
 
-pub struct SoundPlayer {
-    manager: AudioManager,
-    // sounds for different sound types
-    sounds: HashMap<String, StaticSoundData>,
+impl SoundType {
+    pub const ALL: [SoundType; 7] = [
+        SoundType::Fire,
+        SoundType::Explode,
+        SoundType::MultiFire,
+        SoundType::Wave,
+        SoundType::Load,
+        SoundType::PlayerHit,
+        SoundType::Pickup,
+    ];
+
+    // Section name used for this sound in the sound manifest.
+    pub fn name(self) -> &'static str {
+        match self {
+            SoundType::Fire => "fire",
+            SoundType::Explode => "explode",
+            SoundType::MultiFire => "multi_fire",
+            SoundType::Wave => "wave",
+            SoundType::Load => "load",
+            SoundType::PlayerHit => "player_hit",
+            SoundType::Pickup => "pickup",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<SoundType> {
+        SoundType::ALL.into_iter().find(|sound_type| sound_type.name() == name)
+    }
 }
 
-fn read_assets_dir() -> Result<Vec<PathBuf>> {
-    let mut ogg_files:Vec<PathBuf> = Vec::new();
-    if let Ok(entries) = fs::read_dir("assets") {
-        for entry in entries.flatten() {
-            let path = entry.path();
-            if path.as_path().extension().and_then(|s| s.to_str()) == Some("ogg") {
-                ogg_files.push(path);
-            }
+// Mixer tracks. Every sound event is routed to one of them by the manifest,
+// and each has its own volume on top of the master volume.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Track {
+    Sfx,
+    Ui,
+    Music,
+}
+
+impl Track {
+    pub const ALL: [Track; 3] = [Track::Sfx, Track::Ui, Track::Music];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Track::Sfx => "sfx",
+            Track::Ui => "ui",
+            Track::Music => "music",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Track> {
+        Track::ALL.into_iter().find(|track| track.name() == name)
+    }
+}
+
+// Linear volumes between 0 and 1, as shown in the settings menu and stored in
+// the `[audio]` section of the config file.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AudioSettings {
+    pub master: f32,
+    pub sfx: f32,
+    pub ui: f32,
+    pub music: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings {
+            master: 1.0,
+            sfx: 1.0,
+            ui: 1.0,
+            music: 1.0,
+        }
+    }
+}
+
+impl AudioSettings {
+    pub const KEYS: [&'static str; 4] = ["master", "sfx", "ui", "music"];
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut f32> {
+        match key {
+            "master" => Some(&mut self.master),
+            "sfx" => Some(&mut self.sfx),
+            "ui" => Some(&mut self.ui),
+            "music" => Some(&mut self.music),
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<f32> {
+        match key {
+            "master" => Some(self.master),
+            "sfx" => Some(self.sfx),
+            "ui" => Some(self.ui),
+            "music" => Some(self.music),
+            _ => None,
+        }
+    }
+
+    pub fn track(&self, track: Track) -> f32 {
+        match track {
+            Track::Sfx => self.sfx,
+            Track::Ui => self.ui,
+            Track::Music => self.music,
         }
     }
-    Ok(ogg_files)
 }
 
-fn random_name(prefix: &str, min: u8, max: u8) -> String {
-    let index = min + rand::random::<u8>() % (max - min);
-    format!("{}{:02}", prefix, index)
+fn to_decibels(volume: f32) -> Decibels {
+    if volume <= 0.001 {
+        Decibels::SILENCE
+    } else {
+        Decibels(20.0 * volume.log10())
+    }
+}
+
+const VOLUME_TWEEN: Duration = Duration::from_millis(50);
+const DUCK_TWEEN: Duration = Duration::from_millis(150);
+const STEAL_TWEEN: Duration = Duration::from_millis(30); // fade-out of a voice cut off by a newer one
+
+const MAX_COALESCE_BOOST: f32 = 9.0; // decibels
+
+// Positional sounds are panned by their horizontal offset from the listener
+// and get quieter with distance, in world units.
+const PAN_DISTANCE: f32 = 640.0; // offset at which a sound is fully to one side
+const MAX_PAN: f32 = 0.8; // keep a little of every sound in both speakers
+const NEAR_DISTANCE: f32 = 150.0; // no attenuation closer than this
+const FAR_DISTANCE: f32 = 1200.0;
+const MAX_ATTENUATION: f32 = 12.0; // decibels, reached at FAR_DISTANCE
+
+fn tween(duration: Duration) -> Tween {
+    Tween {
+        duration,
+        ..Default::default()
+    }
+}
+
+// Plays of one sound queued during the current frame.
+struct Pending {
+    sound_type: SoundType,
+    count: u32,
+    positioned: u32,
+    pos_sum: Vec2,
+}
+
+impl Pending {
+    fn pos(&self) -> Option<Vec2> {
+        (self.positioned > 0).then(|| self.pos_sum / self.positioned as f32)
+    }
+}
+
+// The null backend accepts every call and plays nothing. It is used when
+// sound is muted and whenever the audio device or the sound assets can't be
+// opened, so the game never depends on having working audio.
+enum AudioBackend {
+    Kira {
+        manager: Box<AudioManager<DefaultBackend>>,
+        tracks: HashMap<Track, TrackHandle>,
+        bank: SoundBank,
+        music: Option<Box<MusicPlayer>>,
+    },
+    Null,
+}
+
+pub struct SoundPlayer {
+    backend: AudioBackend,
+    settings: AudioSettings,
+    last_played: HashMap<SoundType, Instant>,
+    pending: Vec<Pending>,
+    voices: HashMap<SoundType, VecDeque<StaticSoundHandle>>,
+    pub listener: Vec2,
+    // the SFX track is turned down by `duck` decibels until this time
+    duck: f32,
+    duck_until: Option<Instant>,
 }
 
 impl SoundPlayer {
-    pub fn new() -> Result<Self> {
-        let manager = AudioManager::<DefaultBackend>::new(AudioManagerSettings::default()).unwrap();
-
-        // load sounds from assets returning a vector of paths
-        if let Ok(ogg_files) = read_assets_dir() {
-            let mut sounds = HashMap::new();
-            for path in &ogg_files {
-                println!("Found sound file: {}", path.display());
-                let file_name = path.file_stem().unwrap().to_string_lossy().to_string();
-                let data = fs::read(path).unwrap();
-                let sound_data = StaticSoundData::from_cursor(Cursor::new(data)).unwrap();
-                sounds.insert(file_name, sound_data);
+    pub fn new(mute: bool, assets: &Assets) -> Self {
+        if mute {
+            return SoundPlayer::null();
+        }
+
+        match SoundPlayer::open(assets) {
+            Ok(player) => player,
+            Err(err) => {
+                eprintln!("warning: audio disabled: {:#}", err);
+                SoundPlayer::null()
+            }
+        }
+    }
+
+    fn open(assets: &Assets) -> Result<Self> {
+        let mut manager = AudioManager::<DefaultBackend>::new(AudioManagerSettings::default())
+            .context("opening audio device")?;
+        let mut tracks = HashMap::new();
+        for track in Track::ALL {
+            let handle = manager
+                .add_sub_track(TrackBuilder::new())
+                .with_context(|| format!("creating the {} mixer track", track.name()))?;
+            tracks.insert(track, handle);
+        }
+        let bank = SoundBank::load(assets)?;
+        // broken music only costs the music, not the sound effects
+        let music = MusicPlayer::load(assets).unwrap_or_else(|err| {
+            eprintln!("warning: music disabled: {:#}", err);
+            None
+        });
+        let music = music.map(Box::new);
+
+        Ok(SoundPlayer::with_backend(AudioBackend::Kira {
+            manager: Box::new(manager),
+            tracks,
+            bank,
+            music,
+        }))
+    }
+
+    pub fn null() -> Self {
+        SoundPlayer::with_backend(AudioBackend::Null)
+    }
+
+    fn with_backend(backend: AudioBackend) -> Self {
+        SoundPlayer {
+            backend,
+            settings: AudioSettings::default(),
+            last_played: HashMap::new(),
+            pending: Vec::new(),
+            voices: HashMap::new(),
+            listener: Vec2::ZERO,
+            duck: 0.0,
+            duck_until: None,
+        }
+    }
+
+    // Loads the sound or the music manifest again if `changed` says one of
+    // the files it uses changed, and returns whether anything was reloaded.
+    // Music only restarts when it was reloaded. On error the sounds that were
+    // already loaded stay in use.
+    pub fn reload(&mut self, assets: &Assets, changed: impl Fn(&str) -> bool) -> Result<bool> {
+        let AudioBackend::Kira { bank, music, .. } = &mut self.backend else {
+            return Ok(false);
+        };
+        let reload_sounds = bank.files().any(&changed);
+        let reload_music = match music {
+            Some(music) => music.files().any(&changed),
+            None => changed(music::MANIFEST_FILE),
+        };
+        let new_bank = if reload_sounds { Some(SoundBank::load(assets)?) } else { None };
+        let new_music = if reload_music { Some(MusicPlayer::load(assets)?) } else { None };
+
+        if let Some(new_bank) = new_bank {
+            *bank = new_bank;
+        }
+        if let Some(new_music) = new_music {
+            if let Some(music) = music {
+                music.stop();
             }
-            Ok(SoundPlayer { manager, sounds })
-        } else {
-            Err(anyhow::anyhow!("Failed to read assets directory"))
+            *music = new_music.map(Box::new);
+        }
+        Ok(reload_sounds || reload_music)
+    }
+
+    pub fn set_settings(&mut self, settings: AudioSettings) {
+        self.settings = settings;
+        if let AudioBackend::Kira { manager, .. } = &mut self.backend {
+            manager
+                .main_track()
+                .set_volume(to_decibels(settings.master), tween(VOLUME_TWEEN));
+        }
+        for track in Track::ALL {
+            self.apply_track_volume(track, VOLUME_TWEEN);
         }
     }
 
-    pub fn play(&mut self, sound_type: SoundType) {
-        // select a random sound from self.sounds based on the sound type
-        let (sound_name, volume) = match sound_type {
-            SoundType::Fire => (random_name("Laser_", 7, 10), -6.0),
-            SoundType::Explode => (random_name("Laser_", 3, 7), 1.0),
-            SoundType::MultiFire => (random_name("Laser_", 0, 3), -8.0),
-            SoundType::Wave => (random_name("incoming_", 0, 5), 10.0),
-            SoundType::Load => ("part".to_string(), -8.0),
+    fn apply_track_volume(&mut self, track: Track, duration: Duration) {
+        let AudioBackend::Kira { tracks, .. } = &mut self.backend else {
+            return;
         };
-       
-        let sound = match self.sounds.get(&sound_name) {
-            Some(sound) => sound.clone().volume(volume),
+        let mut volume = to_decibels(self.settings.track(track));
+        if track == Track::Sfx && self.duck_until.is_some() {
+            volume = Decibels((volume.0 + self.duck).max(Decibels::SILENCE.0));
+        }
+        if let Some(handle) = tracks.get_mut(&track) {
+            handle.set_volume(volume, tween(duration));
+        }
+    }
+
+    // Plays the sounds queued since the last call and lifts the SFX ducking
+    // once the sound that caused it has finished. Called once per frame.
+    pub fn update(&mut self) {
+        for pending in std::mem::take(&mut self.pending) {
+            self.start(pending);
+        }
+
+        if self.duck_until.is_some_and(|until| Instant::now() >= until) {
+            self.duck_until = None;
+            self.apply_track_volume(Track::Sfx, DUCK_TWEEN);
+        }
+
+        if let AudioBackend::Kira {
+            tracks,
+            music: Some(music),
+            ..
+        } = &mut self.backend
+        {
+            if let Some(track) = tracks.get_mut(&Track::Music) {
+                music.update(track);
+            }
+        }
+    }
+
+    // How hectic the game is, from 0 to 1. Decides which music layers play.
+    pub fn set_intensity(&mut self, intensity: f32) {
+        if let AudioBackend::Kira { music: Some(music), .. } = &mut self.backend {
+            music.set_intensity(intensity);
+        }
+    }
+
+    // Announces a new wave with the music stinger, or with the `wave` sound
+    // when there is no music.
+    pub fn wave_started(&mut self) {
+        if let AudioBackend::Kira {
+            tracks,
+            music: Some(music),
+            ..
+        } = &mut self.backend
+        {
+            if let Some(track) = tracks.get_mut(&Track::Music) {
+                if music.play_stinger(track) {
+                    return;
+                }
+            }
+        }
+        self.play(SoundType::Wave, None);
+    }
+
+    // Sounds requested during a frame are only queued here, so that a burst
+    // of identical events (a super bang clearing a crowd) can be played as
+    // one voice in `update`. Sounds with a world position are placed relative
+    // to `listener`; a merged burst is heard from the average position.
+    pub fn play(&mut self, sound_type: SoundType, pos: Option<Vec2>) {
+        if let AudioBackend::Null = self.backend {
+            return;
+        }
+        let index = match self.pending.iter().position(|pending| pending.sound_type == sound_type) {
+            Some(index) => index,
             None => {
-                eprintln!("Sound not found: {}", sound_name);
-                return;
+                self.pending.push(Pending {
+                    sound_type,
+                    count: 0,
+                    positioned: 0,
+                    pos_sum: Vec2::ZERO,
+                });
+                self.pending.len() - 1
             }
         };
-        let _ = self.manager.play(sound);
+        let pending = &mut self.pending[index];
+        pending.count += 1;
+        if let Some(pos) = pos {
+            pending.positioned += 1;
+            pending.pos_sum += pos;
+        }
+    }
+
+    // Stereo panning and extra attenuation in decibels for a sound at `pos`.
+    fn placement(&self, pos: Option<Vec2>) -> (Panning, f32) {
+        let Some(pos) = pos else {
+            return (Panning::CENTER, 0.0);
+        };
+        let offset = pos - self.listener;
+        let pan = (offset.x / PAN_DISTANCE).clamp(-1.0, 1.0) * MAX_PAN;
+        let distance = offset.magnitude();
+        let far = ((distance - NEAR_DISTANCE) / (FAR_DISTANCE - NEAR_DISTANCE)).clamp(0.0, 1.0);
+        (Panning(pan), -far * MAX_ATTENUATION)
+    }
+
+    fn start(&mut self, pending: Pending) {
+        let Pending { sound_type, count, .. } = pending;
+        let (panning, attenuation) = self.placement(pending.pos());
+        let AudioBackend::Kira { tracks, bank, .. } = &mut self.backend else {
+            return;
+        };
+        let Some(event) = bank.get(sound_type) else {
+            return;
+        };
+
+        let now = Instant::now();
+        if let Some(last) = self.last_played.get(&sound_type) {
+            if now.duration_since(*last).as_secs_f32() < event.cooldown {
+                return;
+            }
+        }
+        self.last_played.insert(sound_type, now);
+
+        // events that don't get a layer of their own make the playback louder
+        let layers = count.min(event.layers);
+        let boost = (3.0 * (count as f32 / layers as f32).log2()).min(MAX_COALESCE_BOOST);
+
+        let voices = self.voices.entry(sound_type).or_default();
+        voices.retain(|voice| voice.state() != PlaybackState::Stopped);
+
+        let mut duck_until = None;
+        for _ in 0..layers {
+            if event.voices > 0 && voices.len() >= event.voices {
+                if let Some(mut oldest) = voices.pop_front() {
+                    oldest.stop(tween(STEAL_TWEEN));
+                }
+            }
+
+            let clip = event.pick();
+            let pitch = event.random_pitch();
+            let sound = clip
+                .data
+                .volume(event.volume + boost + attenuation)
+                .playback_rate(pitch)
+                .panning(panning);
+            if let Some(Ok(voice)) = tracks.get_mut(&event.track).map(|track| track.play(sound)) {
+                voices.push_back(voice);
+            }
+            let until = now + clip.data.duration().div_f64(pitch);
+            duck_until = Some(duck_until.map_or(until, |current: Instant| current.max(until)));
+        }
+
+        if let (true, Some(until)) = (event.duck < 0.0, duck_until) {
+            self.duck = event.duck;
+            self.duck_until = Some(self.duck_until.map_or(until, |current| current.max(until)));
+            self.apply_track_volume(Track::Sfx, DUCK_TWEEN);
+        }
     }
 }
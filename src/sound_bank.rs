@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+
+use anyhow::{anyhow, bail, Context, Result};
+use kira::sound::static_sound::StaticSoundData;
+
+use crate::assets::Assets;
+use crate::ini;
+use crate::sound::{SoundType, Track};
+use crate::synth;
+
+pub const MANIFEST_FILE: &str = "sounds.ini";
+
+pub struct Clip {
+    pub data: StaticSoundData,
+    pub weight: f32,
+}
+
+// Everything needed to play one kind of sound: the clips to pick from and how
+// to play them.
+pub struct SoundEvent {
+    pub clips: Vec<Clip>,
+    pub volume: f32, // decibels
+    pub pitch: (f64, f64), // playback rate range
+    pub cooldown: f32, // minimum seconds between two plays
+    pub voices: usize, // most instances playing at once, 0 for no limit
+    pub layers: u32, // most clips layered when several plays land in one frame
+    pub track: Track,
+    pub duck: f32, // decibels the SFX track is lowered by while this plays
+}
+
+impl SoundEvent {
+    // Picks a clip at random, proportionally to the clip weights.
+    pub fn pick(&self) -> &Clip {
+        let total: f32 = self.clips.iter().map(|clip| clip.weight).sum();
+        let mut roll = rand::random::<f32>() * total;
+        for clip in &self.clips {
+            if roll < clip.weight {
+                return clip;
+            }
+            roll -= clip.weight;
+        }
+        &self.clips[self.clips.len() - 1]
+    }
+
+    pub fn random_pitch(&self) -> f64 {
+        let (min, max) = self.pitch;
+        min + rand::random::<f64>() * (max - min)
+    }
+}
+
+pub struct SoundBank {
+    events: HashMap<SoundType, SoundEvent>,
+    files: Vec<String>, // the manifest and every clip it lists
+}
+
+fn parse_range(value: &str) -> Option<(f64, f64)> {
+    match value.split_once("..") {
+        Some((min, max)) => {
+            let (min, max) = (min.trim().parse().ok()?, max.trim().parse().ok()?);
+            (min > 0.0 && min <= max).then_some((min, max))
+        }
+        None => value.trim().parse().ok().filter(|pitch| *pitch > 0.0).map(|pitch| (pitch, pitch)),
+    }
+}
+
+fn load_clip(assets: &Assets, spec: &str, files: &mut Vec<String>) -> Result<Clip> {
+    let (file, weight) = match spec.split_once(':') {
+        Some((file, weight)) => {
+            let weight = weight
+                .trim()
+                .parse::<f32>()
+                .ok()
+                .filter(|weight| *weight > 0.0)
+                .ok_or_else(|| anyhow!("invalid weight `{}` for {}", weight.trim(), file.trim()))?;
+            (file.trim(), weight)
+        }
+        None => (spec.trim(), 1.0),
+    };
+    files.push(file.to_string());
+
+    if !assets.exists(file) {
+        bail!("missing clip {}", assets.describe(file));
+    }
+    let bytes = assets.read(file)?;
+    let data = StaticSoundData::from_cursor(Cursor::new(bytes))
+        .map_err(|err| anyhow!("could not decode {}: {}", assets.describe(file), err))?;
+
+    Ok(Clip { data, weight })
+}
+
+impl SoundBank {
+    // Loads `sounds.ini` together with every clip it lists, and
+    // renders the synthesized sounds. All problems are collected and reported
+    // at once, so a broken manifest or a missing file shows up at startup
+    // instead of halfway through a game.
+    pub fn load(assets: &Assets) -> Result<Self> {
+        let manifest = assets.describe(MANIFEST_FILE);
+        let entries = ini::parse(&assets.read_to_string(MANIFEST_FILE)?)
+            .with_context(|| format!("parsing {}", manifest))?;
+
+        let mut events = HashMap::new();
+        let mut files = vec![MANIFEST_FILE.to_string()];
+        let mut errors = Vec::new();
+        let presets = synth::parse_presets(&entries, &mut errors);
+
+        for sound_type in SoundType::ALL {
+            let section = sound_type.name();
+            let mut event = SoundEvent {
+                clips: Vec::new(),
+                volume: 0.0,
+                pitch: (1.0, 1.0),
+                cooldown: 0.0,
+                voices: 0,
+                layers: 1,
+                track: Track::Sfx,
+                duck: 0.0,
+            };
+            let mut failed = false;
+
+            for entry in entries.iter().filter(|entry| entry.section == section) {
+                let line = entry.line;
+                match entry.key.as_str() {
+                    "clips" => {
+                        for spec in entry.value.split(',').filter(|spec| !spec.trim().is_empty()) {
+                            match load_clip(assets, spec, &mut files) {
+                                Ok(clip) => event.clips.push(clip),
+                                Err(err) => {
+                                    failed = true;
+                                    errors.push(format!("line {}: {}", line, err));
+                                }
+                            }
+                        }
+                    }
+                    "synth" => match presets.get(&entry.value) {
+                        Some(params) => {
+                            for _ in 0..params.variants {
+                                event.clips.push(Clip {
+                                    data: params.randomized().sound_data(),
+                                    weight: 1.0,
+                                });
+                            }
+                        }
+                        None => {
+                            failed = true;
+                            errors.push(format!("line {}: unknown synth preset `{}`", line, entry.value));
+                        }
+                    },
+                    "volume" => match entry.value.parse() {
+                        Ok(volume) => event.volume = volume,
+                        Err(_) => errors.push(format!("line {}: invalid volume `{}`", line, entry.value)),
+                    },
+                    "pitch" => match parse_range(&entry.value) {
+                        Some(pitch) => event.pitch = pitch,
+                        None => errors.push(format!("line {}: invalid pitch range `{}`", line, entry.value)),
+                    },
+                    "cooldown" => match entry.value.parse::<f32>() {
+                        Ok(cooldown) if cooldown >= 0.0 => event.cooldown = cooldown,
+                        _ => errors.push(format!("line {}: invalid cooldown `{}`", line, entry.value)),
+                    },
+                    "voices" => match entry.value.parse() {
+                        Ok(voices) => event.voices = voices,
+                        Err(_) => errors.push(format!("line {}: invalid voices `{}`", line, entry.value)),
+                    },
+                    "layers" => match entry.value.parse() {
+                        Ok(layers) if layers > 0 => event.layers = layers,
+                        _ => errors.push(format!("line {}: invalid layers `{}`", line, entry.value)),
+                    },
+                    "track" => match Track::from_name(&entry.value) {
+                        Some(track) => event.track = track,
+                        None => errors.push(format!("line {}: unknown track `{}`", line, entry.value)),
+                    },
+                    "duck" => match entry.value.parse::<f32>() {
+                        Ok(duck) if duck <= 0.0 => event.duck = duck,
+                        _ => errors.push(format!("line {}: invalid duck `{}`", line, entry.value)),
+                    },
+                    key => errors.push(format!("line {}: unknown key `{}` in [{}]", line, key, section)),
+                }
+            }
+
+            if event.clips.is_empty() && !failed {
+                errors.push(format!("[{}] has no clips or synth", section));
+            }
+            events.insert(sound_type, event);
+        }
+
+        let mut unknown: Vec<&str> = Vec::new();
+        for entry in &entries {
+            let known = SoundType::from_name(&entry.section).is_some() || entry.section.starts_with("synth.");
+            if !known && !unknown.contains(&entry.section.as_str()) {
+                unknown.push(&entry.section);
+                errors.push(format!("line {}: unknown sound event [{}]", entry.line, entry.section));
+            }
+        }
+
+        if !errors.is_empty() {
+            bail!("invalid sound manifest {}:\n  {}", manifest, errors.join("\n  "));
+        }
+        Ok(SoundBank { events, files })
+    }
+
+    // The manifest and every clip it lists.
+    pub fn files(&self) -> impl Iterator<Item = &str> {
+        self.files.iter().map(String::as_str)
+    }
+
+    pub fn get(&self, sound_type: SoundType) -> Option<&SoundEvent> {
+        self.events.get(&sound_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn shipped_manifest_loads() {
+        let assets = Assets::new(Some(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets"))).unwrap();
+        let bank = SoundBank::load(&assets).unwrap();
+        for sound_type in SoundType::ALL {
+            assert!(!bank.get(sound_type).unwrap().clips.is_empty(), "{:?} has no clips", sound_type);
+        }
+    }
+}
@@ -0,0 +1,548 @@
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use speedy2d::color::Color;
+use speedy2d::dimen::{UVec2, Vec2};
+use speedy2d::font::{Font, TextLayout, TextOptions};
+use speedy2d::shape::Rectangle;
+use speedy2d::window::{WindowHandler, WindowHelper, WindowStartupInfo};
+use speedy2d::Graphics2D;
+
+use crate::bullet::Bullet;
+use crate::canvas::{Canvas, View};
+use crate::config::GameConfig;
+use crate::enemy::Enemy;
+use crate::game_entity::GameEntity;
+use crate::player::Player;
+use crate::trail::Trail;
+use crate::COL_ENEMY;
+
+pub const DEFAULT_PORT: u16 = 7878;
+
+const SNAPSHOT_RATE: f32 = 20.0; // snapshots broadcast per second
+const POS_SCALE: f32 = 8.0; // positions and radii are sent in 1/8 px units
+const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+const QUEUED_SNAPSHOTS: usize = 2; // more than this waiting to be sent and new ones are dropped
+const WRITE_TIMEOUT: Duration = Duration::from_millis(500); // before a stalled spectator is dropped
+
+const FRAME_KEY: u8 = 0;
+const FRAME_DELTA: u8 = 1;
+
+const CHANGED_POS: u8 = 0b0001;
+const CHANGED_RADIUS: u8 = 0b0010;
+const CHANGED_ANGLE: u8 = 0b0100;
+const CHANGED_COLOR: u8 = 0b1000;
+
+// Quantized entity state, with the id of the enemy or bullet it belongs to. Server and client both keep these exact integer
+// values as the delta base, so the two sides never drift apart.
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct EntityState {
+    pub id: u32,
+    pub x: i32,
+    pub y: i32,
+    pub radius: i32,
+    pub angle: u16,
+    pub color: u32,
+}
+
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct Snapshot {
+    pub arena_width: u32,
+    pub arena_height: u32,
+    pub player: EntityState,
+    pub enemies: Vec<EntityState>,
+    pub bullets: Vec<EntityState>,
+}
+
+impl EntityState {
+    fn new(id: u32, pos: Vec2, radius: f32, angle: f32, color: Color) -> Self {
+        let to_u8 = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u32;
+        EntityState {
+            id,
+            x: (pos.x * POS_SCALE).round() as i32,
+            y: (pos.y * POS_SCALE).round() as i32,
+            radius: (radius * POS_SCALE).round() as i32,
+            angle: ((angle.rem_euclid(TAU) / TAU) * 65536.0) as u32 as u16,
+            color: to_u8(color.r()) << 24 | to_u8(color.g()) << 16 | to_u8(color.b()) << 8 | to_u8(color.a()),
+        }
+    }
+
+    fn pos(&self) -> Vec2 {
+        Vec2::new(self.x as f32 / POS_SCALE, self.y as f32 / POS_SCALE)
+    }
+
+    fn radius(&self) -> f32 {
+        self.radius as f32 / POS_SCALE
+    }
+
+    fn angle(&self) -> f32 {
+        self.angle as f32 / 65536.0 * TAU
+    }
+
+    fn color(&self) -> Color {
+        Color::from_int_rgba(
+            (self.color >> 24) as u8,
+            (self.color >> 16) as u8,
+            (self.color >> 8) as u8,
+            self.color as u8,
+        )
+    }
+}
+
+impl Snapshot {
+    pub fn capture(arena_size: Vec2, player: &Player, enemies: &[Enemy], bullets: &[Bullet]) -> Self {
+        Snapshot {
+            arena_width: arena_size.x.round() as u32,
+            arena_height: arena_size.y.round() as u32,
+            player: EntityState::new(0, player.pos, player.radius, player.angle, player.color),
+            enemies: enemies
+                .iter()
+                .map(|e| EntityState::new(e.id, e.pos, e.radius, 0.0, e.color))
+                .collect(),
+            bullets: bullets
+                .iter()
+                .map(|b| EntityState::new(b.id, b.pos, b.radius, 0.0, b.color))
+                .collect(),
+        }
+    }
+
+    // Encodes this snapshot as a length-prefixed frame. With a base, every
+    // entity is written as the difference to the entity with the same id in
+    // the base, which for moving circles is usually one or two bytes per field.
+    fn encode(&self, base: Option<&Snapshot>) -> Vec<u8> {
+        let mut out = vec![0; 4];
+        match base {
+            Some(base) => {
+                out.push(FRAME_DELTA);
+                write_varint(&mut out, self.arena_width);
+                write_varint(&mut out, self.arena_height);
+                write_entity_delta(&mut out, &base.player, &self.player);
+                write_list_delta(&mut out, &base.enemies, &self.enemies);
+                write_list_delta(&mut out, &base.bullets, &self.bullets);
+            }
+            None => {
+                out.push(FRAME_KEY);
+                write_varint(&mut out, self.arena_width);
+                write_varint(&mut out, self.arena_height);
+                write_entity_delta(&mut out, &EntityState::default(), &self.player);
+                write_list_delta(&mut out, &[], &self.enemies);
+                write_list_delta(&mut out, &[], &self.bullets);
+            }
+        }
+        let len = (out.len() - 4) as u32;
+        out[..4].copy_from_slice(&len.to_le_bytes());
+        out
+    }
+
+    fn decode(payload: &[u8], base: Option<&Snapshot>) -> Result<Self> {
+        let mut reader = Reader { data: payload, pos: 0 };
+        let empty = Snapshot::default();
+        let base = match reader.u8()? {
+            FRAME_KEY => &empty,
+            FRAME_DELTA => match base {
+                Some(base) => base,
+                None => bail!("received delta frame without a keyframe"),
+            },
+            kind => bail!("unknown frame kind {}", kind),
+        };
+
+        let arena_width = reader.varint()?;
+        let arena_height = reader.varint()?;
+        let player = read_entity_delta(&mut reader, &base.player)?;
+        let enemies = read_list_delta(&mut reader, &base.enemies)?;
+        let bullets = read_list_delta(&mut reader, &base.bullets)?;
+        Ok(Snapshot {
+            arena_width,
+            arena_height,
+            player,
+            enemies,
+            bullets,
+        })
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_signed(out: &mut Vec<u8>, value: i32) {
+    write_varint(out, ((value << 1) ^ (value >> 31)) as u32);
+}
+
+fn write_entity_delta(out: &mut Vec<u8>, old: &EntityState, new: &EntityState) {
+    let mut flags = 0;
+    if old.x != new.x || old.y != new.y {
+        flags |= CHANGED_POS;
+    }
+    if old.radius != new.radius {
+        flags |= CHANGED_RADIUS;
+    }
+    if old.angle != new.angle {
+        flags |= CHANGED_ANGLE;
+    }
+    if old.color != new.color {
+        flags |= CHANGED_COLOR;
+    }
+
+    out.push(flags);
+    if flags & CHANGED_POS != 0 {
+        write_signed(out, new.x.wrapping_sub(old.x));
+        write_signed(out, new.y.wrapping_sub(old.y));
+    }
+    if flags & CHANGED_RADIUS != 0 {
+        write_signed(out, new.radius.wrapping_sub(old.radius));
+    }
+    if flags & CHANGED_ANGLE != 0 {
+        write_signed(out, new.angle.wrapping_sub(old.angle) as i16 as i32);
+    }
+    if flags & CHANGED_COLOR != 0 {
+        write_varint(out, new.color);
+    }
+}
+
+fn by_id(entities: &[EntityState]) -> HashMap<u32, &EntityState> {
+    entities.iter().map(|entity| (entity.id, entity)).collect()
+}
+
+// Ids go out as the difference to the previous one, which is small as
+// entities keep the order they were spawned in. Entities that are new since
+// the base are written in full.
+fn write_list_delta(out: &mut Vec<u8>, old: &[EntityState], new: &[EntityState]) {
+    write_varint(out, new.len() as u32);
+    let old = by_id(old);
+    let empty = EntityState::default();
+    let mut last_id = 0;
+    for entity in new {
+        write_signed(out, entity.id.wrapping_sub(last_id) as i32);
+        last_id = entity.id;
+        write_entity_delta(out, old.get(&entity.id).copied().unwrap_or(&empty), entity);
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn u8(&mut self) -> Result<u8> {
+        match self.data.get(self.pos) {
+            Some(byte) => {
+                self.pos += 1;
+                Ok(*byte)
+            }
+            None => bail!("truncated snapshot frame"),
+        }
+    }
+
+    fn varint(&mut self) -> Result<u32> {
+        let mut value = 0u32;
+        for shift in (0..35).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as u32) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("malformed varint in snapshot frame")
+    }
+
+    fn signed(&mut self) -> Result<i32> {
+        let value = self.varint()?;
+        Ok((value >> 1) as i32 ^ -((value & 1) as i32))
+    }
+}
+
+fn read_entity_delta(reader: &mut Reader, old: &EntityState) -> Result<EntityState> {
+    let flags = reader.u8()?;
+    let mut entity = *old;
+    if flags & CHANGED_POS != 0 {
+        entity.x = entity.x.wrapping_add(reader.signed()?);
+        entity.y = entity.y.wrapping_add(reader.signed()?);
+    }
+    if flags & CHANGED_RADIUS != 0 {
+        entity.radius = entity.radius.wrapping_add(reader.signed()?);
+    }
+    if flags & CHANGED_ANGLE != 0 {
+        entity.angle = entity.angle.wrapping_add(reader.signed()? as i16 as u16);
+    }
+    if flags & CHANGED_COLOR != 0 {
+        entity.color = reader.varint()?;
+    }
+    Ok(entity)
+}
+
+fn read_list_delta(reader: &mut Reader, old: &[EntityState]) -> Result<Vec<EntityState>> {
+    let len = reader.varint()? as usize;
+    if len > reader.data.len() {
+        bail!("entity count {} exceeds frame size", len);
+    }
+    let old = by_id(old);
+    let empty = EntityState::default();
+    let mut last_id = 0u32;
+    let mut entities = Vec::with_capacity(len);
+    for _ in 0..len {
+        let id = last_id.wrapping_add(reader.signed()? as u32);
+        last_id = id;
+        let mut entity = read_entity_delta(reader, old.get(&id).copied().unwrap_or(&empty))?;
+        entity.id = id;
+        entities.push(entity);
+    }
+    Ok(entities)
+}
+
+// Broadcasts world snapshots to every connected spectator at a fixed rate.
+// Accepting, encoding and writing happen on a background thread so a slow
+// client never stalls the game loop: snapshots that thread can't keep up with
+// are dropped, and a spectator that stops reading is disconnected.
+pub struct SpectateServer {
+    sender: SyncSender<Snapshot>,
+    elapsed: f32,
+}
+
+impl SpectateServer {
+    // Only spectators on this machine can connect unless `public` is set.
+    pub fn start(port: u16, public: bool) -> Result<Self> {
+        let host = if public { Ipv4Addr::UNSPECIFIED } else { Ipv4Addr::LOCALHOST };
+        let listener = TcpListener::bind((host, port))?;
+        listener.set_nonblocking(true)?;
+        println!("Spectator server listening on {}:{}", host, port);
+
+        let (sender, receiver) = sync_channel::<Snapshot>(QUEUED_SNAPSHOTS);
+        thread::spawn(move || {
+            let mut clients: Vec<TcpStream> = Vec::new();
+            let mut base: Option<Snapshot> = None;
+
+            for snapshot in receiver {
+                let mut new_clients = Vec::new();
+                while let Ok((stream, addr)) = listener.accept() {
+                    println!("Spectator connected: {}", addr);
+                    if stream.set_nonblocking(false).is_ok() && stream.set_write_timeout(Some(WRITE_TIMEOUT)).is_ok() {
+                        let _ = stream.set_nodelay(true);
+                        new_clients.push(stream);
+                    }
+                }
+
+                if !clients.is_empty() {
+                    let frame = snapshot.encode(base.as_ref());
+                    clients.retain_mut(|client| client.write_all(&frame).is_ok());
+                }
+
+                // late joiners start from a keyframe; afterwards every client
+                // shares the same base and receives the same delta frames
+                if !new_clients.is_empty() {
+                    let frame = snapshot.encode(None);
+                    new_clients.retain_mut(|client| client.write_all(&frame).is_ok());
+                    clients.append(&mut new_clients);
+                }
+
+                base = Some(snapshot);
+            }
+        });
+
+        Ok(SpectateServer { sender, elapsed: 0.0 })
+    }
+
+    pub fn update(&mut self, dt: f32, arena_size: Vec2, player: &Player, enemies: &[Enemy], bullets: &[Bullet]) {
+        self.elapsed += dt;
+        if self.elapsed < 1.0 / SNAPSHOT_RATE {
+            return;
+        }
+        self.elapsed %= 1.0 / SNAPSHOT_RATE;
+        let _ = self.sender.try_send(Snapshot::capture(arena_size, player, enemies, bullets));
+    }
+}
+
+fn read_frames(mut stream: TcpStream, latest: Arc<Mutex<Option<Snapshot>>>) -> Result<()> {
+    let mut base: Option<Snapshot> = None;
+    let mut len_bytes = [0u8; 4];
+    loop {
+        stream.read_exact(&mut len_bytes)?;
+        let len = u32::from_le_bytes(len_bytes) as usize;
+        if len > MAX_FRAME_LEN {
+            bail!("snapshot frame of {} bytes is too large", len);
+        }
+        let mut payload = vec![0; len];
+        stream.read_exact(&mut payload)?;
+
+        let snapshot = Snapshot::decode(&payload, base.as_ref())?;
+        *latest.lock().unwrap() = Some(snapshot.clone());
+        base = Some(snapshot);
+    }
+}
+
+// Read-only client: connects to a spectator server and renders the most
+// recent snapshot it received.
+pub struct SpectatorWindowHandler {
+    font: Font,
+    latest: Arc<Mutex<Option<Snapshot>>>,
+    window_size: UVec2,
+    background_color: Color,
+}
+
+impl SpectatorWindowHandler {
+    pub fn connect(addr: &str, font: Font) -> Result<Self> {
+        let addr = match addr.to_socket_addrs()?.next() {
+            Some(addr) => addr,
+            None => bail!("could not resolve {}", addr),
+        };
+        let stream = TcpStream::connect(addr)?;
+        let _ = stream.set_nodelay(true);
+        println!("Watching {}", addr);
+
+        let latest = Arc::new(Mutex::new(None));
+        let thread_latest = latest.clone();
+        thread::spawn(move || {
+            if let Err(err) = read_frames(stream, thread_latest) {
+                eprintln!("Spectator connection closed: {}", err);
+            }
+        });
+
+        Ok(SpectatorWindowHandler {
+            font,
+            latest,
+            window_size: UVec2::new(1280, 960),
+            background_color: Color::from_int_rgba(0, 0, 0, 50),
+        })
+    }
+
+    fn display_text(&self, canvas: &mut Canvas, text: &str, pos: Vec2) {
+        let formatted_text_block = self.font.layout_text(text, canvas.font_size(32.0), TextOptions::new());
+        canvas.draw_text(pos, Color::WHITE, &formatted_text_block);
+    }
+}
+
+impl WindowHandler for SpectatorWindowHandler {
+    fn on_start(&mut self, helper: &mut WindowHelper<()>, info: WindowStartupInfo) {
+        helper.set_title("Color Bang! (spectating)");
+        self.window_size = *info.viewport_size_pixels();
+    }
+
+    fn on_resize(&mut self, _helper: &mut WindowHelper<()>, size_pixels: UVec2) {
+        self.window_size = size_pixels;
+    }
+
+    fn on_draw(&mut self, helper: &mut WindowHelper, graphics: &mut Graphics2D) {
+        let snapshot = self.latest.lock().unwrap().clone();
+        let arena_size = match &snapshot {
+            Some(snapshot) => Vec2::new(snapshot.arena_width as f32, snapshot.arena_height as f32),
+            None => Vec2::new(1280.0, 960.0),
+        };
+
+        // the arena size can change between snapshots, so clear the letterbox
+        // bars every frame and only smear the arena itself
+        graphics.clear_screen(Color::BLACK);
+        let mut canvas = Canvas::new(graphics, View::letterbox(self.window_size, arena_size));
+        canvas.clip_to(arena_size);
+        canvas.draw_rectangle(&Rectangle::new(Vec2::ZERO, arena_size), self.background_color);
+
+        match snapshot {
+            Some(snapshot) => {
+                let mut player = Player::new(snapshot.player.pos(), &GameConfig::default());
+                player.radius = snapshot.player.radius();
+                player.angle = snapshot.player.angle();
+                player.color = snapshot.player.color();
+                player.draw(&mut canvas);
+
+                for state in &snapshot.enemies {
+                    let enemy = Enemy {
+                        id: state.id,
+                        pos: state.pos(),
+                        vel: Vec2::ZERO,
+                        radius: state.radius(),
+                        color: state.color(),
+                        tone: state.color(),
+                        mark: None,
+                        layer: COL_ENEMY,
+                        mask: 0,
+                        damage: 0.0,
+                        death_radius: 0.0,
+                        trail: Trail::default(),
+                    };
+                    enemy.draw(&mut canvas);
+                }
+
+                for state in &snapshot.bullets {
+                    let mut bullet = Bullet::new(state.pos(), Vec2::ZERO, &GameConfig::default());
+                    bullet.radius = state.radius();
+                    bullet.color = state.color();
+                    bullet.draw(&mut canvas);
+                }
+
+                self.display_text(&mut canvas, "SPECTATING", Vec2::new(20.0, 50.0));
+            }
+            None => {
+                self.display_text(
+                    &mut canvas,
+                    "Waiting for server...",
+                    Vec2::new(arena_size.x / 2.0 - 200.0, arena_size.y / 2.0),
+                );
+            }
+        }
+        canvas.clear_clip();
+
+        helper.request_redraw();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entity(id: u32, x: f32, y: f32) -> EntityState {
+        EntityState::new(id, Vec2::new(x, y), 10.0, 0.0, Color::from_rgb(0.2, 0.4, 0.6))
+    }
+
+    fn snapshot(enemies: Vec<EntityState>) -> Snapshot {
+        Snapshot {
+            arena_width: 1280,
+            arena_height: 960,
+            player: EntityState::new(0, Vec2::new(640.0, 480.0), 20.0, 1.0, Color::WHITE),
+            enemies,
+            bullets: vec![entity(7, 100.5, 200.25)],
+        }
+    }
+
+    #[test]
+    fn keyframe_round_trips() {
+        let original = snapshot(vec![entity(1, 10.0, 20.0), entity(2, -30.0, 4000.0)]);
+        let frame = original.encode(None);
+        assert_eq!(u32::from_le_bytes(frame[..4].try_into().unwrap()) as usize, frame.len() - 4);
+        assert_eq!(Snapshot::decode(&frame[4..], None).unwrap(), original);
+    }
+
+    #[test]
+    fn deltas_follow_entities_by_id() {
+        let base = snapshot(vec![entity(1, 10.0, 20.0), entity(2, 30.0, 40.0), entity(3, 50.0, 60.0)]);
+        // the first enemy died, the others kept still and a new one spawned
+        let next = snapshot(vec![entity(2, 30.0, 40.0), entity(3, 50.0, 60.0), entity(9, 70.0, 80.0)]);
+
+        let frame = next.encode(Some(&base));
+        assert_eq!(Snapshot::decode(&frame[4..], Some(&base)).unwrap(), next);
+
+        // the enemies that kept still cost an id step and an empty set of
+        // flags each, where matching by index would have resent both
+        let only_new = snapshot(vec![entity(9, 70.0, 80.0)]).encode(Some(&base));
+        assert_eq!(frame.len() - only_new.len(), 2 * 2);
+    }
+
+    #[test]
+    fn rejects_broken_frames() {
+        let frame = snapshot(vec![entity(1, 10.0, 20.0)]).encode(None);
+        assert!(Snapshot::decode(&frame[4..frame.len() - 1], None).is_err());
+        let delta = snapshot(vec![]).encode(Some(&Snapshot::default()));
+        assert!(Snapshot::decode(&delta[4..], None).is_err());
+        assert!(Snapshot::decode(&[5], None).is_err());
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context, Result};
+use kira::sound::static_sound::{StaticSoundData, StaticSoundSettings};
+use kira::Frame;
+
+use crate::assets::Assets;
+use crate::ini;
+use crate::sound_bank::MANIFEST_FILE;
+
+pub const SAMPLE_RATE: u32 = 44100;
+const AMPLITUDE: f32 = 0.5;
+const NOISE_STEPS: f32 = 32.0; // new noise values per oscillator period, as in sfxr
+const MAX_VARIANTS: usize = 16; // each one is rendered and kept in memory
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Waveform {
+    Square,
+    Saw,
+    Sine,
+    Noise,
+}
+
+impl Waveform {
+    fn parse(name: &str) -> Option<Waveform> {
+        match name {
+            "square" => Some(Waveform::Square),
+            "saw" => Some(Waveform::Saw),
+            "sine" => Some(Waveform::Sine),
+            "noise" => Some(Waveform::Noise),
+            _ => None,
+        }
+    }
+}
+
+// An sfxr-style sound: one oscillator with a sliding pitch, shaped by an
+// attack/sustain/decay envelope. Times are in seconds.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SynthParams {
+    pub wave: Waveform,
+    pub frequency: f32, // starting pitch in Hz
+    pub slide: f32, // pitch change in octaves per second
+    pub duty: f32, // fraction of a square wave period spent high
+    pub attack: f32,
+    pub sustain: f32,
+    pub decay: f32,
+    pub punch: f32, // extra volume at the start of the sustain
+    pub randomize: f32, // how much each variant differs, 0 to 1
+    pub variants: usize, // rendered versions to pick from per play
+}
+
+impl Default for SynthParams {
+    fn default() -> Self {
+        SynthParams {
+            wave: Waveform::Square,
+            frequency: 440.0,
+            slide: 0.0,
+            duty: 0.5,
+            attack: 0.0,
+            sustain: 0.1,
+            decay: 0.1,
+            punch: 0.0,
+            randomize: 0.0,
+            variants: 1,
+        }
+    }
+}
+
+impl SynthParams {
+    fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        if key == "wave" {
+            self.wave = Waveform::parse(value)
+                .ok_or_else(|| anyhow!("unknown wave `{}`, expected square, saw, sine or noise", value))?;
+            return Ok(());
+        }
+        if key == "variants" {
+            self.variants = value
+                .parse()
+                .ok()
+                .filter(|variants| (1..=MAX_VARIANTS).contains(variants))
+                .ok_or_else(|| anyhow!("variants must be between 1 and {}, not `{}`", MAX_VARIANTS, value))?;
+            return Ok(());
+        }
+
+        let number: f32 = value
+            .parse()
+            .map_err(|_| anyhow!("invalid {} `{}`", key, value))?;
+        let (field, min, max) = match key {
+            "frequency" => (&mut self.frequency, 20.0, 20000.0),
+            "slide" => (&mut self.slide, -20.0, 20.0),
+            "duty" => (&mut self.duty, 0.0, 1.0),
+            "attack" => (&mut self.attack, 0.0, 10.0),
+            "sustain" => (&mut self.sustain, 0.0, 10.0),
+            "decay" => (&mut self.decay, 0.0, 10.0),
+            "punch" => (&mut self.punch, 0.0, 1.0),
+            "randomize" => (&mut self.randomize, 0.0, 1.0),
+            _ => bail!("unknown key `{}`", key),
+        };
+        if !(min..=max).contains(&number) {
+            bail!("{} must be between {} and {}", key, min, max);
+        }
+        *field = number;
+        Ok(())
+    }
+
+    // A copy with pitch, slide and length nudged by up to `randomize`.
+    pub fn randomized(&self) -> SynthParams {
+        let jitter = |amount: f32| (rand::random::<f32>() * 2.0 - 1.0) * self.randomize * amount;
+        SynthParams {
+            frequency: self.frequency * (1.0 + jitter(0.3)),
+            slide: self.slide + jitter(1.0),
+            sustain: self.sustain * (1.0 + jitter(0.5)),
+            decay: self.decay * (1.0 + jitter(0.5)),
+            ..*self
+        }
+    }
+
+    pub fn render(&self) -> Vec<f32> {
+        let rate = SAMPLE_RATE as f32;
+        let length = ((self.attack + self.sustain + self.decay) * rate) as usize;
+        let mut samples = Vec::with_capacity(length);
+        let mut phase = 0.0f32;
+        let mut noise = 0.0;
+        let mut noise_step = usize::MAX;
+
+        for index in 0..length {
+            let t = index as f32 / rate;
+            let envelope = if t < self.attack {
+                t / self.attack
+            } else if t < self.attack + self.sustain {
+                1.0 + self.punch * (1.0 - (t - self.attack) / self.sustain)
+            } else {
+                1.0 - (t - self.attack - self.sustain) / self.decay
+            };
+
+            let frequency = (self.frequency * (self.slide * t).exp2()).clamp(20.0, 20000.0);
+            phase = (phase + frequency / rate).fract();
+
+            let value = match self.wave {
+                Waveform::Square => {
+                    if phase < self.duty {
+                        1.0
+                    } else {
+                        -1.0
+                    }
+                }
+                Waveform::Saw => 1.0 - 2.0 * phase,
+                Waveform::Sine => (phase * TAU).sin(),
+                Waveform::Noise => {
+                    let step = (phase * NOISE_STEPS) as usize;
+                    if step != noise_step {
+                        noise_step = step;
+                        noise = rand::random::<f32>() * 2.0 - 1.0;
+                    }
+                    noise
+                }
+            };
+            samples.push(value * envelope * AMPLITUDE);
+        }
+        samples
+    }
+
+    pub fn sound_data(&self) -> StaticSoundData {
+        StaticSoundData {
+            sample_rate: SAMPLE_RATE,
+            frames: self.render().into_iter().map(Frame::from_mono).collect::<Arc<[Frame]>>(),
+            settings: StaticSoundSettings::default(),
+            slice: None,
+        }
+    }
+}
+
+// Reads the `[synth.NAME]` sections of a sound manifest. Problems are added
+// to `errors` so they are reported together with the rest of the manifest.
+pub fn parse_presets(entries: &[ini::Entry], errors: &mut Vec<String>) -> HashMap<String, SynthParams> {
+    let mut presets = HashMap::new();
+    for entry in entries {
+        let Some(name) = entry.section.strip_prefix("synth.") else {
+            continue;
+        };
+        let params: &mut SynthParams = presets.entry(name.to_string()).or_default();
+        if let Err(err) = params.set(&entry.key, &entry.value) {
+            errors.push(format!("line {}: [{}] {}", entry.line, entry.section, err));
+        }
+    }
+    presets
+}
+
+// Renders one preset from the sound manifest, without any randomization, to
+// a 16-bit mono WAV file.
+pub fn render_preset(assets: &Assets, name: &str, out: &Path) -> Result<()> {
+    let manifest = assets.describe(MANIFEST_FILE);
+    let entries = ini::parse(&assets.read_to_string(MANIFEST_FILE)?)
+        .with_context(|| format!("parsing {}", manifest))?;
+    let mut errors = Vec::new();
+    let presets = parse_presets(&entries, &mut errors);
+    if !errors.is_empty() {
+        bail!("invalid sound manifest {}:\n  {}", manifest, errors.join("\n  "));
+    }
+
+    let Some(params) = presets.get(name) else {
+        let mut names: Vec<&str> = presets.keys().map(String::as_str).collect();
+        names.sort();
+        bail!("no synth preset `{}` in {} (available: {})", name, manifest, names.join(", "));
+    };
+
+    let samples = params.render();
+    write_wav(out, &samples).with_context(|| format!("writing {}", out.display()))?;
+    println!(
+        "Rendered `{}` to {} ({:.2} s)",
+        name,
+        out.display(),
+        samples.len() as f32 / SAMPLE_RATE as f32
+    );
+    Ok(())
+}
+
+fn write_wav(path: &Path, samples: &[f32]) -> Result<()> {
+    let data_size = samples.len() as u32 * 2;
+    let mut bytes = Vec::with_capacity(44 + data_size as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_size).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes()); // format chunk size
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // mono
+    bytes.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    bytes.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes()); // bytes per second
+    bytes.extend_from_slice(&2u16.to_le_bytes()); // bytes per frame
+    bytes.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_size.to_le_bytes());
+    for sample in samples {
+        let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        bytes.extend_from_slice(&sample.to_le_bytes());
+    }
+    fs::write(path, bytes)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn presets(text: &str) -> (HashMap<String, SynthParams>, Vec<String>) {
+        let mut errors = Vec::new();
+        let presets = parse_presets(&ini::parse(text).unwrap(), &mut errors);
+        (presets, errors)
+    }
+
+    #[test]
+    fn variants_are_bounded() {
+        let (found, errors) = presets("[synth.laser]\nvariants = 16\n[synth.noise]\nvariants = 100000\nwave = noise\n");
+        assert_eq!(found["laser"].variants, 16);
+        assert_eq!(errors, ["line 4: [synth.noise] variants must be between 1 and 16, not `100000`"]);
+        let (_, errors) = presets("[synth.laser]\nvariants = 0\n");
+        assert_eq!(errors.len(), 1);
+    }
+}
@@ -0,0 +1,86 @@
+use speedy2d::color::Color;
+use speedy2d::dimen::Vec2;
+
+use crate::canvas::Canvas;
+
+const TRAIL_LENGTH: usize = 10; // positions remembered, one per frame
+// A step longer than this is a jump, such as an enemy wrapping around the
+// arena, and starts the trail over rather than streaking across the screen.
+const MAX_STEP: f32 = 50.0;
+
+// How moving things are made to look fast.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EffectsStyle {
+    // the original look: the last frames are faded out by a translucent
+    // background, smearing everything alike
+    Smear,
+    // the screen is cleared every frame, bullets and enemies leave trails of
+    // their own and bullets glow
+    Trails,
+}
+
+impl EffectsStyle {
+    pub fn parse(name: &str) -> Option<EffectsStyle> {
+        match name.to_ascii_lowercase().as_str() {
+            "smear" => Some(EffectsStyle::Smear),
+            "trails" => Some(EffectsStyle::Trails),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EffectsStyle::Smear => "smear",
+            EffectsStyle::Trails => "trails",
+        }
+    }
+}
+
+// The recent positions of an entity in a ring buffer.
+#[derive(Clone)]
+pub struct Trail {
+    points: [Vec2; TRAIL_LENGTH],
+    head: usize, // where the next position goes
+    len: usize,
+}
+
+impl Default for Trail {
+    fn default() -> Self {
+        Trail {
+            points: [Vec2::ZERO; TRAIL_LENGTH],
+            head: 0,
+            len: 0,
+        }
+    }
+}
+
+impl Trail {
+    pub fn push(&mut self, pos: Vec2) {
+        let last = self.iter().next();
+        if last.is_some_and(|last| (pos - last).magnitude_squared() > MAX_STEP * MAX_STEP) {
+            self.len = 0;
+        }
+        self.points[self.head] = pos;
+        self.head = (self.head + 1) % TRAIL_LENGTH;
+        self.len = (self.len + 1).min(TRAIL_LENGTH);
+    }
+
+    // Newest first.
+    fn iter(&self) -> impl Iterator<Item = Vec2> + '_ {
+        (1..=self.len).map(move |age| self.points[(self.head + TRAIL_LENGTH - age) % TRAIL_LENGTH])
+    }
+
+    // A tapering line through the recorded positions that fades out with age.
+    pub fn draw(&self, canvas: &mut Canvas, width: f32, color: Color) {
+        let mut points = self.iter();
+        let Some(mut newer) = points.next() else {
+            return;
+        };
+        for (age, older) in points.enumerate() {
+            let fade = 1.0 - (age + 1) as f32 / TRAIL_LENGTH as f32;
+            let faded = Color::from_rgba(color.r(), color.g(), color.b(), color.a() * fade * 0.6);
+            canvas.draw_line(newer, older, width * fade, faded);
+            newer = older;
+        }
+    }
+}
@@ -0,0 +1,422 @@
+use speedy2d::color::Color;
+use speedy2d::dimen::Vec2;
+use speedy2d::font::{Font, FormattedTextBlock, TextLayout, TextOptions};
+use speedy2d::shape::Rectangle;
+use speedy2d::window::VirtualKeyCode;
+
+use crate::canvas::Canvas;
+
+const PANEL_WIDTH: f32 = 560.0;
+const ROW_HEIGHT: f32 = 44.0;
+const TEXT_SIZE: f32 = 26.0;
+const TITLE_SIZE: f32 = 48.0;
+const HINT_SIZE: f32 = 18.0;
+const PADDING: f32 = 10.0;
+const SLIDER_WIDTH: f32 = 200.0;
+
+const BACKDROP_COLOR: Color = Color::from_rgba(0.0, 0.0, 0.0, 0.8);
+const FOCUS_COLOR: Color = Color::from_rgba(1.0, 1.0, 1.0, 0.1);
+const DIM_COLOR: Color = Color::from_rgba(1.0, 1.0, 1.0, 0.6);
+const TRACK_COLOR: Color = Color::from_rgba(1.0, 1.0, 1.0, 0.2);
+
+// Moving around the menus, from the keyboard or a gamepad.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Nav {
+    Up,
+    Down,
+    Left,
+    Right,
+    Activate,
+    Back,
+}
+
+impl Nav {
+    pub fn from_key(key: VirtualKeyCode) -> Option<Nav> {
+        match key {
+            VirtualKeyCode::Up => Some(Nav::Up),
+            VirtualKeyCode::Down => Some(Nav::Down),
+            VirtualKeyCode::Left => Some(Nav::Left),
+            VirtualKeyCode::Right => Some(Nav::Right),
+            VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => Some(Nav::Activate),
+            VirtualKeyCode::Escape => Some(Nav::Back),
+            _ => None,
+        }
+    }
+}
+
+// Window and gamepad events for the menus, gathered between two frames.
+#[derive(Default)]
+pub struct UiInput {
+    mouse: Option<Vec2>, // in HUD coordinates
+    mouse_moved: bool,
+    mouse_down: bool,
+    clicked: bool,
+    nav: Vec<Nav>,
+    typed: String,
+    erased: usize,
+    key: Option<VirtualKeyCode>,
+}
+
+impl UiInput {
+    pub fn mouse_move(&mut self, pos: Vec2) {
+        self.mouse = Some(pos);
+        self.mouse_moved = true;
+    }
+
+    pub fn mouse_down(&mut self) {
+        self.mouse_down = true;
+        self.clicked = true;
+    }
+
+    pub fn mouse_up(&mut self) {
+        self.mouse_down = false;
+    }
+
+    pub fn key_down(&mut self, key: VirtualKeyCode) {
+        self.nav.extend(Nav::from_key(key));
+        if key == VirtualKeyCode::Backspace {
+            self.erased += 1;
+        }
+        self.key = Some(key);
+    }
+
+    pub fn nav(&mut self, nav: Nav) {
+        self.nav.push(nav);
+    }
+
+    pub fn char_typed(&mut self, c: char) {
+        if !c.is_control() {
+            self.typed.push(c);
+        }
+    }
+
+    // The last key pressed, for rebinding it. Whatever it would have done in
+    // the menu is dropped.
+    pub fn take_key(&mut self) -> Option<VirtualKeyCode> {
+        self.nav.clear();
+        self.typed.clear();
+        self.erased = 0;
+        self.key.take()
+    }
+
+    fn end_frame(&mut self) {
+        self.mouse_moved = false;
+        self.clicked = false;
+        self.nav.clear();
+        self.typed.clear();
+        self.erased = 0;
+        self.key = None;
+    }
+}
+
+// The part of the menus that lasts between frames: input and focus. Widgets
+// are numbered in the order they are drawn, so a screen has to draw the same
+// ones every frame for the focus to stay put.
+#[derive(Default)]
+pub struct Ui {
+    pub input: UiInput,
+    focus: usize,
+    count: usize, // focusable widgets in the last frame
+}
+
+impl Ui {
+    pub fn reset_focus(&mut self) {
+        self.focus = 0;
+    }
+
+    // Starts a frame of a menu screen: a column in the middle of a darkened
+    // screen that widgets are added to from the top.
+    pub fn panel<'a, 'c>(&'a mut self, canvas: &'a mut Canvas<'c>, font: &'a Font, hud_size: Vec2) -> Panel<'a, 'c> {
+        canvas.draw_rectangle(&Rectangle::new(Vec2::ZERO, hud_size), BACKDROP_COLOR);
+
+        let mut nav = std::mem::take(&mut self.input.nav);
+        if self.count > 0 {
+            for step in &nav {
+                match step {
+                    Nav::Up => self.focus = (self.focus + self.count - 1) % self.count,
+                    Nav::Down => self.focus = (self.focus + 1) % self.count,
+                    _ => {}
+                }
+            }
+        }
+        nav.retain(|step| !matches!(step, Nav::Up | Nav::Down));
+
+        Panel {
+            ui: self,
+            canvas,
+            font,
+            nav,
+            left: (hud_size.x - PANEL_WIDTH) / 2.0,
+            y: hud_size.y * 0.15,
+            index: 0,
+        }
+    }
+}
+
+struct Widget {
+    rect: Rectangle,
+    focused: bool,
+    clicked: bool,
+}
+
+// One frame of a menu screen. Each widget draws itself and reports what was
+// done to it.
+pub struct Panel<'a, 'c> {
+    ui: &'a mut Ui,
+    canvas: &'a mut Canvas<'c>,
+    font: &'a Font,
+    nav: Vec<Nav>,
+    left: f32,
+    y: f32,
+    index: usize,
+}
+
+impl Panel<'_, '_> {
+    fn layout(&self, text: &str, size: f32) -> FormattedTextBlock {
+        self.font.layout_text(text, self.canvas.font_size(size), TextOptions::new())
+    }
+
+    fn text(&mut self, text: &str, size: f32, pos: Vec2, color: Color) -> f32 {
+        let layout = self.layout(text, size);
+        self.canvas.draw_text(pos, color, &layout);
+        layout.width() / self.canvas.view.scale
+    }
+
+    // Text aligned to the right edge of the row, and the text's width.
+    fn text_right(&mut self, text: &str, y: f32, color: Color) -> f32 {
+        let layout = self.layout(text, TEXT_SIZE);
+        let width = layout.width() / self.canvas.view.scale;
+        let pos = Vec2::new(self.left + PANEL_WIDTH - PADDING - width, y);
+        self.canvas.draw_text(pos, color, &layout);
+        width
+    }
+
+    fn row_text_y(&self) -> f32 {
+        self.y + (ROW_HEIGHT - TEXT_SIZE) / 2.0
+    }
+
+    fn mouse_in(&self, rect: &Rectangle) -> bool {
+        self.ui.input.mouse.is_some_and(|pos| rect.contains(pos))
+    }
+
+    // Claims the next row for a widget that can take focus. The mouse moves
+    // the focus to whatever it is over.
+    fn widget(&mut self) -> Widget {
+        let rect = Rectangle::new(
+            Vec2::new(self.left, self.y),
+            Vec2::new(self.left + PANEL_WIDTH, self.y + ROW_HEIGHT),
+        );
+        let hovered = self.mouse_in(&rect);
+        if hovered && self.ui.input.mouse_moved {
+            self.ui.focus = self.index;
+        }
+        let focused = self.ui.focus == self.index;
+        if focused {
+            self.canvas.draw_rectangle(&rect, FOCUS_COLOR);
+        }
+        self.index += 1;
+        Widget {
+            rect,
+            focused,
+            clicked: hovered && self.ui.input.clicked,
+        }
+    }
+
+    // Navigation meant for the focused widget.
+    fn take(&mut self, widget: &Widget, nav: Nav) -> bool {
+        if !widget.focused {
+            return false;
+        }
+        match self.nav.iter().position(|pending| *pending == nav) {
+            Some(index) => {
+                self.nav.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn label_color(widget: &Widget) -> Color {
+        if widget.focused {
+            Color::YELLOW
+        } else {
+            Color::WHITE
+        }
+    }
+
+    pub fn title(&mut self, text: &str) {
+        let pos = Vec2::new(self.left, self.y);
+        self.text(text, TITLE_SIZE, pos, Color::WHITE);
+        self.y += TITLE_SIZE + 30.0;
+    }
+
+    pub fn label(&mut self, text: &str, color: Color) {
+        let pos = Vec2::new(self.left + PADDING, self.row_text_y());
+        self.text(text, TEXT_SIZE, pos, color);
+        self.y += ROW_HEIGHT;
+    }
+
+    // Two columns, for tables.
+    pub fn row(&mut self, left: &str, right: &str, color: Color) {
+        let y = self.row_text_y();
+        self.text(left, TEXT_SIZE, Vec2::new(self.left + PADDING, y), color);
+        self.text_right(right, y, color);
+        self.y += ROW_HEIGHT;
+    }
+
+    pub fn hint(&mut self, text: &str) {
+        let pos = Vec2::new(self.left + PADDING, self.y + 10.0);
+        self.text(text, HINT_SIZE, pos, DIM_COLOR);
+        self.y += HINT_SIZE + 20.0;
+    }
+
+    pub fn space(&mut self) {
+        self.y += ROW_HEIGHT / 2.0;
+    }
+
+    // Whether Escape or the gamepad's back button was pressed.
+    pub fn back(&mut self) -> bool {
+        match self.nav.iter().position(|pending| *pending == Nav::Back) {
+            Some(index) => {
+                self.nav.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn button(&mut self, label: &str) -> bool {
+        self.button_with_value(label, "")
+    }
+
+    // A button that shows a value on the right, like a key binding.
+    pub fn button_with_value(&mut self, label: &str, value: &str) -> bool {
+        let widget = self.widget();
+        let y = self.row_text_y();
+        let color = Self::label_color(&widget);
+        self.text(label, TEXT_SIZE, Vec2::new(self.left + PADDING, y), color);
+        self.text_right(value, y, color);
+        self.y += ROW_HEIGHT;
+        widget.clicked || self.take(&widget, Nav::Activate)
+    }
+
+    pub fn toggle(&mut self, label: &str, value: &mut bool) -> bool {
+        let text = if *value { "On" } else { "Off" };
+        let widget = self.widget();
+        let y = self.row_text_y();
+        let color = Self::label_color(&widget);
+        self.text(label, TEXT_SIZE, Vec2::new(self.left + PADDING, y), color);
+        self.text_right(text, y, color);
+        self.y += ROW_HEIGHT;
+
+        // `|` rather than `||`, so every key pressed is used up
+        let pressed = self.take(&widget, Nav::Activate) | self.take(&widget, Nav::Left) | self.take(&widget, Nav::Right);
+        if widget.clicked || pressed {
+            *value = !*value;
+            return true;
+        }
+        false
+    }
+
+    // Left and right step the value; the mouse can drag along the track.
+    pub fn slider(&mut self, label: &str, value: &mut f32, min: f32, max: f32, step: f32) -> bool {
+        let widget = self.widget();
+        let y = self.row_text_y();
+        let color = Self::label_color(&widget);
+        self.text(label, TEXT_SIZE, Vec2::new(self.left + PADDING, y), color);
+
+        let old = *value;
+        let percent = format!("{:.0}%", (*value - min) / (max - min) * 100.0);
+        let width = self.text_right(&percent, y, color).max(60.0);
+        let track_right = self.left + PANEL_WIDTH - PADDING * 2.0 - width;
+        let track = Rectangle::new(
+            Vec2::new(track_right - SLIDER_WIDTH, widget.rect.top_left().y + ROW_HEIGHT / 2.0 - 4.0),
+            Vec2::new(track_right, widget.rect.top_left().y + ROW_HEIGHT / 2.0 + 4.0),
+        );
+
+        if self.take(&widget, Nav::Left) {
+            *value -= step;
+        }
+        if self.take(&widget, Nav::Right) {
+            *value += step;
+        }
+        if let Some(mouse) = self.ui.input.mouse.filter(|_| self.ui.input.mouse_down && widget.focused) {
+            let grab = Rectangle::new(
+                Vec2::new(track.top_left().x - PADDING, widget.rect.top_left().y),
+                Vec2::new(track.bottom_right().x + PADDING, widget.rect.bottom_right().y),
+            );
+            if grab.contains(mouse) {
+                let fraction = (mouse.x - track.top_left().x) / track.width();
+                *value = min + fraction * (max - min);
+            }
+        }
+        // whole steps, so the ends can be reached exactly
+        *value = (((*value - min) / step).round() * step + min).clamp(min, max);
+
+        let fill = (*value - min) / (max - min);
+        self.canvas.draw_rectangle(&track, TRACK_COLOR);
+        let filled = Rectangle::new(
+            *track.top_left(),
+            Vec2::new(track.top_left().x + track.width() * fill, track.bottom_right().y),
+        );
+        self.canvas.draw_rectangle(&filled, color);
+        self.y += ROW_HEIGHT;
+        *value != old
+    }
+
+    // Picks one of a list of options, cycling with left and right (or a
+    // click or activate, which go forwards).
+    pub fn list(&mut self, label: &str, options: &[&str], selected: &mut usize) -> bool {
+        let widget = self.widget();
+        let y = self.row_text_y();
+        let color = Self::label_color(&widget);
+        self.text(label, TEXT_SIZE, Vec2::new(self.left + PADDING, y), color);
+        let current = options.get(*selected).copied().unwrap_or("");
+        self.text_right(&format!("< {} >", current), y, color);
+        self.y += ROW_HEIGHT;
+
+        let count = options.len().max(1);
+        let old = *selected;
+        if self.take(&widget, Nav::Left) {
+            *selected = (*selected + count - 1) % count;
+        }
+        if widget.clicked || self.take(&widget, Nav::Right) || self.take(&widget, Nav::Activate) {
+            *selected = (*selected + 1) % count;
+        }
+        *selected != old
+    }
+
+    // Typing goes into the field while it has focus. Returns true when Enter
+    // is pressed in it.
+    pub fn text_input(&mut self, label: &str, text: &mut String, max_length: usize) -> bool {
+        let widget = self.widget();
+        if widget.focused {
+            for _ in 0..self.ui.input.erased {
+                text.pop();
+            }
+            for c in self.ui.input.typed.chars() {
+                if text.chars().count() < max_length {
+                    text.push(c);
+                }
+            }
+        }
+
+        let y = self.row_text_y();
+        let color = Self::label_color(&widget);
+        self.text(label, TEXT_SIZE, Vec2::new(self.left + PADDING, y), color);
+        let shown = if widget.focused { format!("{}_", text) } else { text.clone() };
+        self.text_right(&shown, y, Color::WHITE);
+        self.y += ROW_HEIGHT;
+        self.take(&widget, Nav::Activate)
+    }
+}
+
+impl Drop for Panel<'_, '_> {
+    fn drop(&mut self) {
+        self.ui.count = self.index;
+        if self.index > 0 {
+            self.ui.focus = self.ui.focus.min(self.index - 1);
+        }
+        self.ui.input.end_frame();
+    }
+}